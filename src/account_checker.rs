@@ -1,25 +1,59 @@
 use {
     crate::{
         cache, config,
-        customers_config::{CustomerConfig, DomainConfig},
+        customers_config::{self, CustomerConfig, DomainConfig},
         errors::Result,
+        public_suffix::SuffixList,
+        registrar::{self, RegistrarAccount, Registrars},
+        storage::LookupRecord,
+        whois::{self, WhoisClient, WhoisServerConfig},
     },
     chrono::{DateTime, Utc},
-    slog::{debug, info, o, Level},
+    chrono_tz::Tz,
+    slog::{debug, info, o, warn, Level},
     slog_unwraps::ResultExt,
-    std::{cmp, collections::HashMap, fmt::Display, io},
+    std::{
+        cmp,
+        collections::{BTreeMap, HashMap},
+        fmt::Display,
+        io,
+        time::Instant,
+    },
 };
 
 pub(crate) struct AccountChecker {
-    whois_client: whois2::Client,
+    whois_client: WhoisClient,
+    registrars: Registrars,
+    suffix_list: SuffixList,
     cache: cache::Cache,
+    pub stats: LookupStats,
+
+    // Whois requests, which not saved to storage yet.
+    lookups: Vec<LookupRecord>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct LookupStats {
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+
+    // Seconds of last whois lookup of domain.
+    pub lookup_durations: HashMap<String, f64>,
 }
 
 impl AccountChecker {
-    pub(crate) fn new() -> AccountChecker {
+    pub(crate) fn new(
+        suffix_list: SuffixList,
+        whois_client: WhoisClient,
+        registrars: Registrars,
+    ) -> AccountChecker {
         return AccountChecker {
-            whois_client: whois2::Client::new(),
+            whois_client,
+            registrars,
+            suffix_list,
             cache: cache::Cache::new(),
+            stats: LookupStats::default(),
+            lookups: Vec::new(),
         };
     }
 
@@ -35,8 +69,14 @@ impl AccountChecker {
             return res;
         }
         for domain in &cust.domains {
-            res.domain_results
-                .insert(domain, self.check_domain(log, domain));
+            let domain_result = self.check_domain(log, domain);
+            if domain_result.is_ok() {
+                let name = self.lookup_name(&domain.domain);
+                if let Some(autorenew) = self.cache.get(&name).and_then(|entry| entry.autorenew) {
+                    res.autorenew.insert(domain, autorenew);
+                }
+            }
+            res.domain_results.insert(domain, domain_result);
         }
         return res;
     }
@@ -52,26 +92,161 @@ impl AccountChecker {
             return Ok(CheckDomainResult::Disabled);
         }
         debug!(log, "Start check");
-        if self.cache.domains_expire.contains_key(&domain.domain) {
-            debug!(log, "Read date of expire from cache");
+        let name = self.lookup_name(&domain.domain);
+        if name != domain.ascii_name() {
+            debug!(log, "Check registrable domain"; "registrable-domain"=>&name);
+        }
+        if let Some(entry) = self.cache.get(&name) {
+            debug!(log, "Read date of expire from cache"; "fetched"=>entry.fetched.to_string());
+            self.stats.cache_hits += 1;
         } else {
-            info!(log, "Get expire date from whois servers");
-            let whois = self
-                .whois_client
-                .get_whois_kv(&domain.domain)
-                .log(log, Level::Error)?;
-            let expire_date = get_paid_till_date(&whois).log(log, Level::Error)?;
-            self.cache
-                .domains_expire
-                .insert(domain.domain.clone(), expire_date);
+            self.stats.cache_misses += 1;
+            // Registrar API is authoritative, whois is fallback.
+            let entry = match self.registrars.account(&domain.account) {
+                Some(account) => self.registrar_lookup(log, &name, &account),
+                None => None,
+            };
+            let entry = match entry {
+                Some(entry) => entry,
+                None => self.whois_lookup(log, &name, domain)?,
+            };
+            self.cache.insert(&name, entry);
         }
-        let expire_date = self.cache.domains_expire[&domain.domain];
+        let expire_date = self.cache.get(&name).unwrap().expire;
         debug!(log, "Expire_date"; "expire"=>expire_date.to_string());
         return Ok(CheckDomainResult::ExpireDate(expire_date));
     }
 
+    fn whois_lookup(
+        &mut self,
+        log: &slog::Logger,
+        name: &str,
+        domain: &DomainConfig,
+    ) -> Result<cache::CacheEntry> {
+        info!(log, "Get expire date from whois servers");
+        let start = Instant::now();
+        let whois = self.lookup(log, name, domain.whois_server.as_ref());
+        self.stats
+            .lookup_durations
+            .insert(name.to_string(), start.elapsed().as_secs_f64());
+        let whois = match whois {
+            Ok(whois) => whois,
+            Err(err) => {
+                self.lookups.push(LookupRecord {
+                    domain: name.to_string(),
+                    server: None,
+                    time: Utc::now(),
+                    expire: None,
+                    error: Some(err.to_string()),
+                    raw: None,
+                });
+                return Err(err);
+            }
+        };
+        let expire_date = parse_expire_date(&whois.text).log(log, Level::Error);
+        self.lookups.push(LookupRecord {
+            domain: name.to_string(),
+            server: Some(whois.server),
+            time: Utc::now(),
+            expire: expire_date.as_ref().ok().cloned(),
+            error: expire_date.as_ref().err().map(|err| err.to_string()),
+            raw: Some(whois.text),
+        });
+        return Ok(cache::CacheEntry {
+            expire: expire_date?,
+            fetched: Utc::now(),
+            autorenew: None,
+        });
+    }
+
+    // Expire date from registrar API. None if request failed: whois is used then.
+    fn registrar_lookup(
+        &mut self,
+        log: &slog::Logger,
+        name: &str,
+        account: &RegistrarAccount,
+    ) -> Option<cache::CacheEntry> {
+        info!(log, "Get expire date from registrar API"; "provider"=>account.provider.as_str());
+        let server = format!("{} API", account.provider);
+        let start = Instant::now();
+        let info = registrar::domain_info(account, name);
+        self.stats
+            .lookup_durations
+            .insert(name.to_string(), start.elapsed().as_secs_f64());
+        match info {
+            Ok(info) => {
+                self.lookups.push(LookupRecord {
+                    domain: name.to_string(),
+                    server: Some(server),
+                    time: Utc::now(),
+                    expire: Some(info.expire),
+                    error: None,
+                    raw: Some(info.raw),
+                });
+                return Some(cache::CacheEntry {
+                    expire: info.expire,
+                    fetched: Utc::now(),
+                    autorenew: info.autorenew,
+                });
+            }
+            Err(err) => {
+                warn!(log, "Registrar API request failed, use whois"; "error"=>err.to_string());
+                self.lookups.push(LookupRecord {
+                    domain: name.to_string(),
+                    server: Some(server),
+                    time: Utc::now(),
+                    expire: None,
+                    error: Some(err.to_string()),
+                    raw: None,
+                });
+                return None;
+            }
+        }
+    }
+
+    // Name of registration for whois lookups and cache: punycode registrable domain.
+    // Subdomains of the same registration share one lookup.
+    pub(crate) fn lookup_name(&self, domain: &str) -> String {
+        return self
+            .suffix_list
+            .registrable_domain(&customers_config::ascii_domain(domain));
+    }
+
+    // Remove domain from cache, returns false if it isn't cached.
+    pub(crate) fn invalidate(&mut self, domain: &str) -> bool {
+        let name = self.lookup_name(domain);
+        return self.cache.remove(&name);
+    }
+
+    // Raw whois response for the domain, without cache.
+    pub(crate) fn lookup(
+        &mut self,
+        log: &slog::Logger,
+        domain: &str,
+        server: Option<&WhoisServerConfig>,
+    ) -> Result<whois::Response> {
+        let res = self
+            .whois_client
+            .lookup(domain, server)
+            .log(log, Level::Error)?;
+        debug!(log, "Whois response received"; "server"=>&res.server);
+        return Ok(res);
+    }
+
+    pub(crate) fn clean_cache(&mut self, now: &DateTime<Utc>, policy: &cache::CachePolicy) {
+        self.cache.clean(now, policy);
+    }
+
+    pub(crate) fn take_lookups(&mut self) -> Vec<LookupRecord> {
+        return std::mem::replace(&mut self.lookups, Vec::new());
+    }
+
+    pub(crate) fn cache_mut(&mut self) -> &mut cache::Cache {
+        return &mut self.cache;
+    }
+
     pub(crate) fn save_state<W: io::Write>(&self, writer: W) -> Result<()> {
-        Ok(serde_yaml::to_writer(writer, &self.cache)?)
+        return cache::write_state(writer, &self.cache);
     }
 
     pub(crate) fn load_state<R: io::Read>(
@@ -79,28 +254,73 @@ impl AccountChecker {
         log: &slog::Logger,
         reader: R,
         now: chrono::DateTime<Utc>,
-        no_cache_days_before_expire: i64,
+        policy: &cache::CachePolicy,
     ) -> Result<()> {
-        self.cache = serde_yaml::from_reader(reader)?;
-        self.cache.clean(&now, no_cache_days_before_expire);
-        info!(log, "Load cache"; "domains-count"=>self.cache.domains_expire.len());
+        self.cache = cache::read_state(reader)?;
+        self.cache.clean(&now, policy);
+        info!(log, "Load cache"; "domains-count"=>self.cache.domains.len());
         return Ok(());
     }
 }
 
 pub(crate) struct CheckAccountResult<'a> {
     domain_results: HashMap<&'a DomainConfig, Result<CheckDomainResult>>,
+
+    // Autorenew reported by registrar API.
+    autorenew: HashMap<&'a DomainConfig, bool>,
 }
 
 impl<'a> CheckAccountResult<'a> {
     fn new() -> Self {
         return CheckAccountResult {
             domain_results: HashMap::new(),
+            autorenew: HashMap::new(),
+        };
+    }
+
+    // Result with given domain results and autorenew reported by registrar API.
+    #[cfg(test)]
+    pub(crate) fn from_results(
+        domain_results: Vec<(&'a DomainConfig, Result<CheckDomainResult>)>,
+        autorenew: Vec<(&'a DomainConfig, bool)>,
+    ) -> Self {
+        return CheckAccountResult {
+            domain_results: domain_results.into_iter().collect(),
+            autorenew: autorenew.into_iter().collect(),
         };
     }
+
+    // Autorenew from registrar API if known, else from customers config.
+    pub(crate) fn autorenew(&self, domain: &DomainConfig) -> bool {
+        return self
+            .autorenew
+            .get(domain)
+            .cloned()
+            .unwrap_or(domain.autorenew);
+    }
+
+    pub(crate) fn domain_results(
+        &self,
+    ) -> impl Iterator<Item = (&'a DomainConfig, &Result<CheckDomainResult>)> {
+        return self
+            .domain_results
+            .iter()
+            .map(|(domain, res)| (*domain, res));
+    }
+
+    // Domains with successfully checked expire date.
+    pub(crate) fn expire_dates(&self) -> impl Iterator<Item = (&'a DomainConfig, &DateTime<Utc>)> {
+        return self
+            .domain_results
+            .iter()
+            .filter_map(|(domain, res)| match res {
+                Ok(CheckDomainResult::ExpireDate(expire)) => Some((*domain, expire)),
+                _ => None,
+            });
+    }
 }
 
-enum CheckDomainResult {
+pub(crate) enum CheckDomainResult {
     ExpireDate(chrono::DateTime<Utc>),
     Disabled,
 }
@@ -115,7 +335,11 @@ impl Display for CheckDomainResult {
     }
 }
 
-pub(crate) fn create_account_report(customer_result: &CheckAccountResult) -> String {
+pub(crate) fn create_account_report(
+    customer_result: &CheckAccountResult,
+    now: &DateTime<Utc>,
+    tz: Tz,
+) -> String {
     use CheckDomainResult::*;
 
     let mut domains: Vec<&DomainConfig> = customer_result
@@ -140,15 +364,21 @@ pub(crate) fn create_account_report(customer_result: &CheckAccountResult) -> Str
     });
     let mut table = vec![];
     for domain_config in domains {
-        let expired_column = match &customer_result.domain_results[domain_config] {
-            Err(err) => format!("{}", err),
-            Ok(res) => format!("{}", res),
-        };
+        let (expired_column, days_left_column) =
+            match &customer_result.domain_results[domain_config] {
+                Err(err) => (format!("{}", err), String::new()),
+                Ok(Disabled) => (Disabled.to_string(), String::new()),
+                Ok(ExpireDate(expire)) => (
+                    format_expire_date(expire, tz),
+                    format_days_left(expire, now),
+                ),
+            };
         table.push([
-            domain_config.domain.clone(),
-            domain_config.account.clone(),
-            expired_column.clone(),
-            domain_config.autorenew.to_string(),
+            domain_config.display_name(),
+            domain_config.account.to_string(),
+            expired_column,
+            days_left_column,
+            customer_result.autorenew(domain_config).to_string(),
         ])
     }
 
@@ -161,6 +391,9 @@ pub(crate) fn create_account_report(customer_result: &CheckAccountResult) -> Str
     let mut expire_column = ascii_table::ColumnConfig::default();
     expire_column.header = "Expired".to_string();
 
+    let mut days_left_column = ascii_table::ColumnConfig::default();
+    days_left_column.header = "Days left".to_string();
+
     let mut autorenew_column = ascii_table::ColumnConfig::default();
     autorenew_column.header = "Autorenew".to_string();
 
@@ -169,11 +402,123 @@ pub(crate) fn create_account_report(customer_result: &CheckAccountResult) -> Str
     table_config.columns.insert(0, domain_column);
     table_config.columns.insert(1, domain_account_column);
     table_config.columns.insert(2, expire_column);
-    table_config.columns.insert(3, autorenew_column);
+    table_config.columns.insert(3, days_left_column);
+    table_config.columns.insert(4, autorenew_column);
     return ascii_table::format_table(&table, &table_config);
 }
 
-fn get_paid_till_date(whois: &HashMap<String, String>) -> Result<DateTime<Utc>> {
+// Domains of all customers grouped by registrar account, sorted by expire date.
+// accounts - registrar/login keys of shown accounts, all if empty.
+// expiring_days - show only domains, which expire in the days or less.
+pub(crate) fn create_accounts_report(
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+    accounts: &[String],
+    expiring_days: Option<i64>,
+    now: &DateTime<Utc>,
+    tz: Tz,
+) -> String {
+    let mut groups: BTreeMap<String, Vec<(Option<DateTime<Utc>>, [String; 5])>> = BTreeMap::new();
+    for (customer, check_result) in results {
+        for (domain, domain_result) in check_result.domain_results() {
+            let key = domain.account.key();
+            if !accounts.is_empty()
+                && !accounts
+                    .iter()
+                    .any(|account| account.to_lowercase() == key.to_lowercase())
+            {
+                continue;
+            }
+            let (expire, expired_column, days_left_column) = match domain_result {
+                Err(err) => (None, err.to_string(), String::new()),
+                Ok(CheckDomainResult::Disabled) => continue,
+                Ok(CheckDomainResult::ExpireDate(expire)) => (
+                    Some(*expire),
+                    format_expire_date(expire, tz),
+                    format_days_left(expire, now),
+                ),
+            };
+            if let Some(days) = expiring_days {
+                match expire {
+                    Some(expire) if days_left(&expire, now) <= days => {}
+                    _ => continue,
+                }
+            }
+            let group = if domain.account.is_empty() {
+                "-".to_string()
+            } else {
+                domain.account.to_string()
+            };
+            groups.entry(group).or_insert_with(Vec::new).push((
+                expire,
+                [
+                    domain.display_name(),
+                    customer.name.clone(),
+                    expired_column,
+                    days_left_column,
+                    check_result.autorenew(domain).to_string(),
+                ],
+            ));
+        }
+    }
+
+    let mut res = String::new();
+    for (account, mut rows) in groups {
+        // Errors first, like in customer report.
+        rows.sort_by(|(expire1, row1), (expire2, row2)| {
+            expire1.cmp(expire2).then_with(|| row1[0].cmp(&row2[0]))
+        });
+        let table: Vec<_> = rows.into_iter().map(|(_, row)| row).collect();
+
+        let mut table_config = ascii_table::TableConfig::default();
+        table_config.width = 140;
+        for (index, header) in ["Domain", "Customer", "Expired", "Days left", "Autorenew"]
+            .iter()
+            .enumerate()
+        {
+            let mut column = ascii_table::ColumnConfig::default();
+            column.header = header.to_string();
+            table_config.columns.insert(index, column);
+        }
+        res += &format!(
+            "Account: {}\n{}\n",
+            account,
+            ascii_table::format_table(&table, &table_config)
+        );
+    }
+    return res;
+}
+
+fn format_expire_date(expire: &DateTime<Utc>, tz: Tz) -> String {
+    return expire
+        .with_timezone(&tz)
+        .format("%Y-%m-%d %H:%M %Z")
+        .to_string();
+}
+
+// Whole days between now and expire, negative if domain already expired.
+pub(crate) fn days_left(expire: &DateTime<Utc>, now: &DateTime<Utc>) -> i64 {
+    return (*expire - *now).num_days();
+}
+
+fn format_days_left(expire: &DateTime<Utc>, now: &DateTime<Utc>) -> String {
+    if expire < now {
+        return format!("EXPIRED {} days ago", (*now - *expire).num_days());
+    }
+    return days_left(expire, now).to_string();
+}
+
+pub(crate) fn parse_expire_date(whois: &str) -> Result<DateTime<Utc>> {
+    // RDAP response is json
+    if whois.trim_start().starts_with('{') {
+        return match whois::rdap_expire_date(whois) {
+            Some(date) => Ok(DateTime::parse_from_rfc3339(&date)?.with_timezone(&Utc)),
+            None => Err(crate::errors::Error::CanFindWhoisField),
+        };
+    }
+    return get_paid_till_date(&whois::key_value(whois));
+}
+
+fn get_paid_till_date(whois: &HashMap<&str, &str>) -> Result<DateTime<Utc>> {
     for key in &vec!["paid-till", "registry expiry date"] {
         if let Some(date) = whois.get(*key) {
             return Ok(DateTime::parse_from_rfc3339(date)?.with_timezone(&chrono::Utc));
@@ -182,19 +527,86 @@ fn get_paid_till_date(whois: &HashMap<String, String>) -> Result<DateTime<Utc>>
     return Err(crate::errors::Error::CanFindWhoisField);
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DomainStatus {
+    Ok,
+    Expiring,
+    Expired,
+    Disabled,
+    Error,
+}
+
+impl DomainStatus {
+    pub(crate) fn as_str(&self) -> &'static str {
+        use DomainStatus::*;
+        match self {
+            Ok => "ok",
+            Expiring => "expiring",
+            Expired => "expired",
+            Disabled => "disabled",
+            Error => "error",
+        }
+    }
+}
+
+// Expiring - if domain expire in expire_soon_days or less.
+pub(crate) fn domain_status(
+    res: &Result<CheckDomainResult>,
+    now: &chrono::DateTime<Utc>,
+    expire_soon_days: i64,
+) -> DomainStatus {
+    match res {
+        Err(_) => DomainStatus::Error,
+        Ok(CheckDomainResult::Disabled) => DomainStatus::Disabled,
+        Ok(CheckDomainResult::ExpireDate(expire)) => {
+            if expire < now {
+                DomainStatus::Expired
+            } else if days_left(expire, now) <= expire_soon_days {
+                DomainStatus::Expiring
+            } else {
+                DomainStatus::Ok
+            }
+        }
+    }
+}
+
+pub(crate) struct AccountSummary {
+    pub expiring: usize,
+    pub expired: usize,
+    pub errors: usize,
+}
+
+impl AccountSummary {
+    pub(crate) fn need_attention(&self) -> bool {
+        return self.expiring > 0 || self.expired > 0 || self.errors > 0;
+    }
+}
+
+pub(crate) fn account_summary(
+    cfg: &config::Config,
+    acc_result: &CheckAccountResult,
+    now: &chrono::DateTime<Utc>,
+) -> AccountSummary {
+    let mut res = AccountSummary {
+        expiring: 0,
+        expired: 0,
+        errors: 0,
+    };
+    for item in acc_result.domain_results.values() {
+        match domain_status(item, now, cfg.expire_soon_days as i64) {
+            DomainStatus::Error => res.errors += 1,
+            DomainStatus::Expired => res.expired += 1,
+            DomainStatus::Expiring => res.expiring += 1,
+            DomainStatus::Ok | DomainStatus::Disabled => {}
+        }
+    }
+    return res;
+}
+
 pub(crate) fn need_attention(
     cfg: &config::Config,
     acc_result: &CheckAccountResult,
     now: &chrono::DateTime<Utc>,
 ) -> bool {
-    return acc_result.domain_results.values().any(|item| match item {
-        Err(_) => true,
-        Ok(res) => match res {
-            CheckDomainResult::Disabled => false,
-            CheckDomainResult::ExpireDate(expire) => {
-                let a: chrono::Duration = *expire - *now;
-                return a.num_days() <= cfg.expire_soon_days as i64;
-            }
-        },
-    });
+    return account_summary(cfg, acc_result, now).need_attention();
 }
@@ -0,0 +1,163 @@
+use {
+    crate::{config::Config, errors::Result, storage::LookupRecord},
+    chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc},
+    flate2::{read::GzDecoder, write::GzEncoder, Compression},
+    slog::{debug, info},
+    std::{
+        fs,
+        io::{self, Read, Write},
+        path::{Path, PathBuf},
+    },
+};
+
+const ARCHIVE_DIR_NAME: &str = "whois-archive";
+const FILE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%.fZ";
+const FILE_EXTENSION: &str = ".txt.gz";
+
+// Archived whois response with headers.
+pub(crate) struct ArchiveEntry {
+    pub time: DateTime<Utc>,
+    pub text: String,
+}
+
+// Raw whois responses, one gzip file per lookup: <state dir>/whois-archive/<domain>/<time>.txt.gz
+pub(crate) struct Archive {
+    dir: PathBuf,
+    retention_days: i64,
+}
+
+impl Archive {
+    pub(crate) fn from_config(cfg: &Config) -> Option<Self> {
+        if cfg.state_file.is_empty() || cfg.whois_archive_retention_days == 0 {
+            return None;
+        }
+        let state_dir = match Path::new(&cfg.state_file).parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        return Some(Archive {
+            dir: state_dir.join(ARCHIVE_DIR_NAME),
+            retention_days: cfg.whois_archive_retention_days as i64,
+        });
+    }
+
+    pub(crate) fn save(&self, log: &slog::Logger, lookups: &[LookupRecord]) -> Result<()> {
+        for lookup in lookups {
+            let raw = match &lookup.raw {
+                Some(raw) => raw,
+                None => continue,
+            };
+            let dir = self.domain_dir(&lookup.domain);
+            fs::create_dir_all(&dir)?;
+            let fname = dir.join(format!(
+                "{}{}",
+                lookup.time.format(FILE_TIME_FORMAT),
+                FILE_EXTENSION
+            ));
+            let mut encoder = GzEncoder::new(fs::File::create(&fname)?, Compression::default());
+            writeln!(encoder, "Domain: {}", lookup.domain)?;
+            if let Some(server) = &lookup.server {
+                writeln!(encoder, "Server: {}", server)?;
+            }
+            writeln!(encoder, "Time: {}", lookup.time.to_rfc3339())?;
+            writeln!(encoder)?;
+            encoder.write_all(raw.as_bytes())?;
+            encoder.finish()?;
+            debug!(log, "Whois response archived"; "file"=>fname.to_string_lossy().to_string());
+        }
+        return Ok(());
+    }
+
+    // Remove responses older then retention period.
+    pub(crate) fn clean(&self, log: &slog::Logger, now: &DateTime<Utc>) -> Result<()> {
+        let domain_dirs = match fs::read_dir(&self.dir) {
+            Ok(dirs) => dirs,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let min_time = *now - Duration::days(self.retention_days);
+        let mut removed = 0;
+        for domain_dir in domain_dirs {
+            let domain_dir = domain_dir?.path();
+            for (time, path) in list_files(&domain_dir)? {
+                if time < min_time {
+                    fs::remove_file(&path)?;
+                    removed += 1;
+                }
+            }
+            // Remove dir of domain without responses, fails if dir isn't empty.
+            let _ = fs::remove_dir(&domain_dir);
+        }
+        if removed > 0 {
+            info!(log, "Old whois responses removed from archive"; "count"=>removed);
+        }
+        return Ok(());
+    }
+
+    // Last count responses of domain, from old to new.
+    pub(crate) fn last(&self, domain: &str, count: usize) -> Result<Vec<ArchiveEntry>> {
+        let mut files = match list_files(&self.domain_dir(domain)) {
+            Ok(files) => files,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        files.sort_by(|(t1, _), (t2, _)| t1.cmp(t2));
+        let skip = files.len().saturating_sub(count);
+        let mut res = Vec::new();
+        for (time, path) in files.into_iter().skip(skip) {
+            let mut text = String::new();
+            GzDecoder::new(fs::File::open(&path)?).read_to_string(&mut text)?;
+            res.push(ArchiveEntry { time, text });
+        }
+        return Ok(res);
+    }
+
+    fn domain_dir(&self, domain: &str) -> PathBuf {
+        return self.dir.join(domain.to_lowercase());
+    }
+}
+
+// Archived files of domain with lookup time, parsed from file name.
+fn list_files(dir: &Path) -> io::Result<Vec<(DateTime<Utc>, PathBuf)>> {
+    let mut res = Vec::new();
+    for item in fs::read_dir(dir)? {
+        let path = item?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.ends_with(FILE_EXTENSION) => name,
+            _ => continue,
+        };
+        let time = &name[..name.len() - FILE_EXTENSION.len()];
+        if let Ok(time) = NaiveDateTime::parse_from_str(time, FILE_TIME_FORMAT) {
+            res.push((Utc.from_utc_datetime(&time), path));
+        }
+    }
+    return Ok(res);
+}
+
+// Print responses one by one or diff of every response with previous.
+pub(crate) fn create_report(entries: &[ArchiveEntry], is_diff: bool) -> String {
+    let mut res = String::new();
+    for (index, entry) in entries.iter().enumerate() {
+        if !is_diff {
+            res += &format!("==> {} <==\n{}\n", entry.time.to_rfc3339(), entry.text);
+            continue;
+        }
+        if index == 0 {
+            continue;
+        }
+        let prev = &entries[index - 1];
+        res += &format!(
+            "--- {}\n+++ {}\n",
+            prev.time.to_rfc3339(),
+            entry.time.to_rfc3339()
+        );
+        for line in diff::lines(&prev.text, &entry.text) {
+            match line {
+                diff::Result::Left(line) => res += &format!("-{}\n", line),
+                diff::Result::Right(line) => res += &format!("+{}\n", line),
+                diff::Result::Both(..) => {}
+            }
+        }
+    }
+    return res;
+}
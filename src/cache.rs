@@ -1,21 +1,110 @@
 use {
-    chrono::{DateTime, Utc},
+    crate::{
+        config::Config,
+        customers_config::ascii_domain,
+        errors::{Error, Result},
+    },
+    chrono::{DateTime, Duration, Utc},
     serde::{Deserialize, Serialize},
-    std::collections::HashMap,
+    serde_yaml::{Mapping, Value},
+    std::{
+        collections::{hash_map::DefaultHasher, HashMap},
+        hash::{Hash, Hasher},
+        io,
+    },
 };
 
+// Version of state file format. Increase it and add migration on change of Cache structure.
+const STATE_VERSION: u64 = 3;
+
+type Migration = fn(&mut Mapping) -> Result<()>;
+
+// MIGRATIONS[n] upgrade state from version n to n + 1.
+const MIGRATIONS: [Migration; STATE_VERSION as usize] = [migrate_v0, migrate_v1, migrate_v2];
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct CacheEntry {
+    pub expire: DateTime<Utc>,
+
+    // Time of whois lookup.
+    pub fetched: DateTime<Utc>,
+
+    // Autorenew reported by registrar API, None for whois lookups.
+    pub autorenew: Option<bool>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Cache {
-    #[serde(with = "domains_expire_serializer")]
-    pub domains_expire: HashMap<String, DateTime<Utc>>,
+    #[serde(with = "domains_serializer")]
+    pub domains: HashMap<String, CacheEntry>,
+}
+
+// When cached expire date must be requested from whois again.
+#[derive(Debug)]
+pub(crate) struct CachePolicy {
+    // Domains, which expire within the days, are in alert window.
+    pub no_cache_days_before_expire: i64,
+
+    // Max age of entry in alert window. 0 - lookup on every check.
+    pub alert_refresh_hours: i64,
+
+    // Max age of other entries. 0 - never expire.
+    pub max_age_days: i64,
+
+    // Random addition to max_age_days, spread lookups of domains, fetched at the same time.
+    pub jitter_hours: i64,
+}
+
+impl CachePolicy {
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        return CachePolicy {
+            no_cache_days_before_expire: cfg.no_cache_days_before_expire,
+            alert_refresh_hours: cfg.cache_alert_refresh_hours as i64,
+            max_age_days: cfg.cache_max_age_days as i64,
+            jitter_hours: cfg.cache_jitter_hours as i64,
+        };
+    }
+
+    fn is_fresh(&self, domain: &str, entry: &CacheEntry, now: &DateTime<Utc>) -> bool {
+        let age = *now - entry.fetched;
+        if (entry.expire - *now).num_days() <= self.no_cache_days_before_expire {
+            return age < Duration::hours(self.alert_refresh_hours);
+        }
+        if self.max_age_days == 0 {
+            return true;
+        }
+        return age < Duration::days(self.max_age_days) + self.jitter(domain, entry);
+    }
+
+    // Jitter is random, but stable for the entry: doesn't change between runs.
+    fn jitter(&self, domain: &str, entry: &CacheEntry) -> Duration {
+        if self.jitter_hours <= 0 {
+            return Duration::zero();
+        }
+        let mut hasher = DefaultHasher::new();
+        domain.hash(&mut hasher);
+        entry.fetched.timestamp().hash(&mut hasher);
+        let seconds = hasher.finish() % (self.jitter_hours as u64 * 3600);
+        return Duration::seconds(seconds as i64);
+    }
 }
 
-mod domains_expire_serializer {
+mod domains_serializer {
+    use super::CacheEntry;
     use chrono::{DateTime, Utc};
-    use serde::{ser::SerializeMap, Deserialize, Deserializer};
+    use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize};
     use std::collections::HashMap;
 
-    type ItemType = HashMap<String, DateTime<Utc>>;
+    type ItemType = HashMap<String, CacheEntry>;
+
+    #[derive(Serialize, Deserialize)]
+    struct RawEntry {
+        expire: String,
+        fetched: String,
+
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        autorenew: Option<bool>,
+    }
 
     pub fn serialize<S>(item: &ItemType, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -23,7 +112,12 @@ mod domains_expire_serializer {
     {
         let mut map = serializer.serialize_map(Some(item.len()))?;
         for (k, v) in item.iter() {
-            map.serialize_entry(k, &v.to_rfc3339())?;
+            let raw = RawEntry {
+                expire: v.expire.to_rfc3339(),
+                fetched: v.fetched.to_rfc3339(),
+                autorenew: v.autorenew,
+            };
+            map.serialize_entry(k, &raw)?;
         }
         map.end()
     }
@@ -32,33 +126,253 @@ mod domains_expire_serializer {
     where
         D: Deserializer<'de>,
     {
-        let map: HashMap<String, String> = HashMap::deserialize(deserializer)?;
+        let map: HashMap<String, RawEntry> = HashMap::deserialize(deserializer)?;
         let mut res = ItemType::with_capacity(map.len());
         for (k, v) in map {
-            let time_fixed = DateTime::parse_from_rfc3339(&v).map_err(serde::de::Error::custom)?;
-            res.insert(k, time_fixed.into());
+            let expire =
+                DateTime::parse_from_rfc3339(&v.expire).map_err(serde::de::Error::custom)?;
+            let fetched =
+                DateTime::parse_from_rfc3339(&v.fetched).map_err(serde::de::Error::custom)?;
+            res.insert(
+                k,
+                CacheEntry {
+                    expire: expire.with_timezone(&Utc),
+                    fetched: fetched.with_timezone(&Utc),
+                    autorenew: v.autorenew,
+                },
+            );
         }
         Ok(res)
     }
 }
 
+// Version 0 is bare Cache without version field. Structure is same as version 1.
+fn migrate_v0(_state: &mut Mapping) -> Result<()> {
+    return Ok(());
+}
+
+// Version 1 has map domains_expire: domain -> expire date, without time of lookup.
+// Treat entries as fetched at migration time.
+fn migrate_v1(state: &mut Mapping) -> Result<()> {
+    let fetched = Value::from(Utc::now().to_rfc3339());
+    let mut domains = Mapping::new();
+    if let Some(Value::Mapping(domains_expire)) = state.remove(&Value::from("domains_expire")) {
+        for (domain, expire) in domains_expire {
+            let mut entry = Mapping::new();
+            entry.insert(Value::from("expire"), expire);
+            entry.insert(Value::from("fetched"), fetched.clone());
+            domains.insert(domain, Value::Mapping(entry));
+        }
+    }
+    state.insert(Value::from("domains"), Value::Mapping(domains));
+    return Ok(());
+}
+
+// Version 2 has domain names as written in customers config. Convert them to punycode.
+fn migrate_v2(state: &mut Mapping) -> Result<()> {
+    let mut domains = Mapping::new();
+    if let Some(Value::Mapping(old_domains)) = state.remove(&Value::from("domains")) {
+        for (domain, entry) in old_domains {
+            let domain = match domain.as_str() {
+                Some(domain) => Value::from(ascii_domain(domain)),
+                None => domain,
+            };
+            domains.insert(domain, entry);
+        }
+    }
+    state.insert(Value::from("domains"), Value::Mapping(domains));
+    return Ok(());
+}
+
+// Read state of any known version and upgrade it to current Cache.
+pub(crate) fn read_state<R: io::Read>(reader: R) -> Result<Cache> {
+    let mut state = match serde_yaml::from_reader(reader)? {
+        Value::Mapping(state) => state,
+        _ => return Err(Error::StateVersionError("state isn't mapping".to_string())),
+    };
+    let version = match state.remove(&Value::from("version")) {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .ok_or_else(|| Error::StateVersionError(format!("bad version: {:?}", version)))?,
+    };
+    if version > STATE_VERSION {
+        return Err(Error::StateVersionError(format!(
+            "version {} is newer then supported {}",
+            version, STATE_VERSION
+        )));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut state)?;
+    }
+    return Ok(serde_yaml::from_value(Value::Mapping(state))?);
+}
+
+pub(crate) fn write_state<W: io::Write>(writer: W, cache: &Cache) -> Result<()> {
+    let mut state = Mapping::new();
+    state.insert(Value::from("version"), Value::from(STATE_VERSION));
+    if let Value::Mapping(fields) = serde_yaml::to_value(cache)? {
+        state.extend(fields);
+    }
+    return Ok(serde_yaml::to_writer(writer, &state)?);
+}
+
 impl Cache {
     pub(crate) fn new() -> Self {
         Cache {
-            domains_expire: HashMap::new(),
+            domains: HashMap::new(),
         }
     }
 
-    pub(crate) fn clean(&mut self, now: &chrono::DateTime<Utc>, no_cache_days_before_expire: i64) {
+    pub(crate) fn clean(&mut self, now: &DateTime<Utc>, policy: &CachePolicy) {
         let mut for_delete = Vec::new();
-        self.domains_expire.iter().for_each(|(k, expire)| {
-            let days = (*expire - *now).num_days();
-            if days <= no_cache_days_before_expire {
+        self.domains.iter().for_each(|(k, entry)| {
+            if !policy.is_fresh(k, entry, now) {
                 for_delete.push(k.clone());
             }
         });
         for domain in &for_delete {
-            self.domains_expire.remove(domain);
+            self.domains.remove(domain);
+        }
+    }
+
+    pub(crate) fn get(&self, domain: &str) -> Option<&CacheEntry> {
+        return self.domains.get(domain);
+    }
+
+    pub(crate) fn insert(&mut self, domain: &str, entry: CacheEntry) {
+        self.domains.insert(domain.to_string(), entry);
+    }
+
+    pub(crate) fn remove(&mut self, domain: &str) -> bool {
+        return self.domains.remove(domain).is_some();
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.domains.clear();
+    }
+
+    pub(crate) fn create_report(&self) -> String {
+        let mut domains: Vec<_> = self.domains.iter().collect();
+        domains.sort_unstable_by(|(d1, e1), (d2, e2)| {
+            e1.expire.cmp(&e2.expire).then_with(|| d1.cmp(d2))
+        });
+        let table: Vec<_> = domains
+            .iter()
+            .map(|(domain, entry)| {
+                [
+                    domain.to_string(),
+                    entry.expire.to_rfc3339(),
+                    entry.fetched.to_rfc3339(),
+                ]
+            })
+            .collect();
+
+        let mut table_config = ascii_table::TableConfig::default();
+        for (index, header) in ["Domain", "Expired", "Fetched"].iter().enumerate() {
+            let mut column = ascii_table::ColumnConfig::default();
+            column.header = header.to_string();
+            table_config.columns.insert(index, column);
+        }
+        return ascii_table::format_table(&table, &table_config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(state: &str) -> Cache {
+        return read_state(state.as_bytes()).unwrap();
+    }
+
+    fn date(date: &str) -> DateTime<Utc> {
+        return DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc);
+    }
+
+    #[test]
+    fn migrate_bare_state() {
+        let before = Utc::now();
+        let cache = read(
+            "
+domains_expire:
+  a.ru: 2027-01-01T00:00:00+00:00
+  Пример.рф: 2027-02-01T00:00:00+00:00
+",
+        );
+        assert_eq!(cache.domains.len(), 2);
+        let entry = cache.get("a.ru").unwrap();
+        assert_eq!(entry.expire, date("2027-01-01T00:00:00+00:00"));
+        assert!(entry.fetched >= before - Duration::seconds(1));
+        assert_eq!(entry.autorenew, None);
+        let entry = cache.get("xn--e1afmkfd.xn--p1ai").unwrap();
+        assert_eq!(entry.expire, date("2027-02-01T00:00:00+00:00"));
+    }
+
+    #[test]
+    fn migrate_v1_state() {
+        let cache = read(
+            "
+version: 1
+domains_expire:
+  b.com: 2028-03-04T05:06:07+00:00
+",
+        );
+        let entry = cache.get("b.com").unwrap();
+        assert_eq!(entry.expire, date("2028-03-04T05:06:07+00:00"));
+        assert!(entry.fetched <= Utc::now());
+    }
+
+    #[test]
+    fn migrate_v2_state() {
+        let cache = read(
+            "
+version: 2
+domains:
+  пример.рф:
+    expire: 2027-02-01T00:00:00+00:00
+    fetched: 2020-01-01T00:00:00+00:00
+  c.org:
+    expire: 2029-01-01T00:00:00+00:00
+    fetched: 2020-01-02T00:00:00+00:00
+",
+        );
+        assert_eq!(cache.domains.len(), 2);
+        assert!(cache.get("пример.рф").is_none());
+        let entry = cache.get("xn--e1afmkfd.xn--p1ai").unwrap();
+        assert_eq!(entry.fetched, date("2020-01-01T00:00:00+00:00"));
+        let entry = cache.get("c.org").unwrap();
+        assert_eq!(entry.expire, date("2029-01-01T00:00:00+00:00"));
+        assert_eq!(entry.fetched, date("2020-01-02T00:00:00+00:00"));
+    }
+
+    #[test]
+    fn write_and_read_current_state() {
+        let mut cache = Cache::new();
+        cache.insert(
+            "a.ru",
+            CacheEntry {
+                expire: date("2027-01-01T00:00:00+00:00"),
+                fetched: date("2020-01-01T00:00:00+00:00"),
+                autorenew: Some(true),
+            },
+        );
+        let mut buf = Vec::new();
+        write_state(&mut buf, &cache).unwrap();
+        assert!(String::from_utf8_lossy(&buf).contains("version: 3"));
+        let entry = *read_state(buf.as_slice()).unwrap().get("a.ru").unwrap();
+        assert_eq!(entry.expire, date("2027-01-01T00:00:00+00:00"));
+        assert_eq!(entry.fetched, date("2020-01-01T00:00:00+00:00"));
+        assert_eq!(entry.autorenew, Some(true));
+    }
+
+    #[test]
+    fn newer_state_version() {
+        match read_state("version: 100\ndomains: {}\n".as_bytes()) {
+            Err(Error::StateVersionError(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
         }
     }
 }
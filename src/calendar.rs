@@ -0,0 +1,181 @@
+use {
+    crate::{
+        account_checker::CheckAccountResult,
+        customers_config::{CustomerConfig, DomainConfig},
+    },
+    chrono::{DateTime, Duration, Utc},
+};
+
+const DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// Max length of content line in octets without line break, RFC 5545 3.1.
+const MAX_LINE_OCTETS: usize = 75;
+
+pub(crate) const CALENDAR_FILE_NAME: &str = "domains.ics";
+
+// iCalendar with renew deadline and expire events for every domain.
+// UIDs depend on customer and domain name only, so calendar clients update events instead of duplicate them.
+pub(crate) fn create_calendar(
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+    expire_soon_days: u16,
+    now: &DateTime<Utc>,
+) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//rekby//whois-monitoring//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+    ];
+    for (customer, check_result) in results {
+        let mut domains: Vec<_> = check_result.expire_dates().collect();
+        domains.sort_unstable_by(|(d1, _), (d2, _)| d1.domain.cmp(&d2.domain));
+        for (domain, expire) in domains {
+            let autorenew = check_result.autorenew(domain);
+            let renew = *expire - Duration::days(expire_soon_days as i64);
+            push_event(
+                &mut lines,
+                "renew",
+                &format!("Renew domain {}", domain.display_name()),
+                customer,
+                domain,
+                autorenew,
+                &renew,
+                now,
+            );
+            push_event(
+                &mut lines,
+                "expire",
+                &format!("Domain {} expires", domain.display_name()),
+                customer,
+                domain,
+                autorenew,
+                expire,
+                now,
+            );
+        }
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let lines: Vec<String> = lines.iter().map(|line| fold_line(line)).collect();
+    let mut res = lines.join("\r\n");
+    res.push_str("\r\n");
+    return res;
+}
+
+fn push_event(
+    lines: &mut Vec<String>,
+    kind: &str,
+    summary: &str,
+    customer: &CustomerConfig,
+    domain: &DomainConfig,
+    autorenew: bool,
+    start: &DateTime<Utc>,
+    now: &DateTime<Utc>,
+) {
+    let description = format!(
+        "Customer: {}\nAccount: {}\nAutorenew: {}",
+        customer.name, domain.account, autorenew
+    );
+    lines.push("BEGIN:VEVENT".to_string());
+    // Customer is in UID: the same domain of several customers is in digest calendar.
+    let customer_id: String =
+        url::form_urlencoded::byte_serialize(customer.name.as_bytes()).collect();
+    lines.push(format!(
+        "UID:{}-{}-{}@whois-monitoring",
+        kind,
+        domain.ascii_name(),
+        customer_id
+    ));
+    lines.push(format!("DTSTAMP:{}", now.format(DATE_FORMAT)));
+    lines.push(format!("DTSTART:{}", start.format(DATE_FORMAT)));
+    lines.push(format!(
+        "DTEND:{}",
+        (*start + Duration::hours(1)).format(DATE_FORMAT)
+    ));
+    lines.push(format!("SUMMARY:{}", escape_text(summary)));
+    lines.push(format!("DESCRIPTION:{}", escape_text(&description)));
+    lines.push("TRANSP:TRANSPARENT".to_string());
+    lines.push("END:VEVENT".to_string());
+}
+
+fn escape_text(text: &str) -> String {
+    return text
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n");
+}
+
+// Split line longer than 75 octets to lines, continuation lines start with space.
+// Multi-octet UTF-8 characters aren't split.
+fn fold_line(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut line_octets = 0;
+    for c in line.chars() {
+        if line_octets + c.len_utf8() > MAX_LINE_OCTETS {
+            res.push_str("\r\n ");
+            line_octets = 1;
+        }
+        res.push(c);
+        line_octets += c.len_utf8();
+    }
+    return res;
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::account_checker::CheckDomainResult};
+
+    #[test]
+    fn fold_short_line() {
+        assert_eq!(
+            fold_line("SUMMARY:Renew domain a.ru"),
+            "SUMMARY:Renew domain a.ru"
+        );
+    }
+
+    #[test]
+    fn fold_long_line() {
+        let line = format!("DESCRIPTION:{}", "a".repeat(100));
+        let folded = fold_line(&line);
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].len(), 75);
+        assert!(parts[1].starts_with(' '));
+        assert_eq!(parts.concat().replacen(' ', "", 1), line);
+    }
+
+    #[test]
+    fn fold_keeps_utf8_chars() {
+        let line = format!("DESCRIPTION:{}", "ж".repeat(100));
+        for part in fold_line(&line).split("\r\n") {
+            assert!(part.len() <= 75);
+        }
+    }
+
+    #[test]
+    fn autorenew_from_registrar() {
+        let customer: CustomerConfig = serde_yaml::from_str(
+            "
+name: c1
+emails: []
+domains:
+  - domain: example.com
+    account: namecheap/user
+",
+        )
+        .unwrap();
+        let domain = &customer.domains[0];
+        let expire = DateTime::parse_from_rfc3339("2027-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let result = CheckAccountResult::from_results(
+            vec![(domain, Ok(CheckDomainResult::ExpireDate(expire)))],
+            vec![(domain, true)],
+        );
+        let calendar = create_calendar(&[(&customer, &result)], 30, &expire);
+        assert!(calendar.contains("Autorenew: true"));
+        assert!(!calendar.contains("Autorenew: false"));
+    }
+}
@@ -0,0 +1,141 @@
+use {
+    crate::{
+        account_checker::{self, CheckAccountResult, CheckDomainResult, DomainStatus},
+        customers_config::DomainConfig,
+        errors,
+    },
+    chrono::{DateTime, Utc},
+};
+
+// Nagios plugin exit codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PluginState {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl PluginState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PluginState::Ok => "OK",
+            PluginState::Warning => "WARNING",
+            PluginState::Critical => "CRITICAL",
+            PluginState::Unknown => "UNKNOWN",
+        }
+    }
+
+    // Worst of two states. Critical is worse then unknown: real problem more important
+    // then problem of check.
+    fn worst(self, other: PluginState) -> PluginState {
+        let weight = |state: PluginState| match state {
+            PluginState::Ok => 0,
+            PluginState::Warning => 1,
+            PluginState::Unknown => 2,
+            PluginState::Critical => 3,
+        };
+        if weight(other) > weight(self) {
+            return other;
+        }
+        return self;
+    }
+}
+
+// One line status with perfdata and plugin state.
+pub(crate) fn create_status(
+    results: &[&CheckAccountResult],
+    now: &DateTime<Utc>,
+    warning_days: i64,
+    critical_days: i64,
+) -> (PluginState, String) {
+    let mut domains: Vec<(&DomainConfig, &errors::Result<CheckDomainResult>)> = results
+        .iter()
+        .flat_map(|res| res.domain_results())
+        .filter(|(_, res)| match res {
+            Ok(CheckDomainResult::Disabled) => false,
+            _ => true,
+        })
+        .collect();
+    domains.sort_by(|(d1, _), (d2, _)| d1.ascii_name().cmp(&d2.ascii_name()));
+    domains.dedup_by(|(d1, _), (d2, _)| d1.ascii_name() == d2.ascii_name());
+
+    if domains.is_empty() {
+        return (
+            PluginState::Unknown,
+            "WHOIS UNKNOWN - no domains for check".to_string(),
+        );
+    }
+
+    let mut state = PluginState::Ok;
+    let mut problems = Vec::new();
+    let mut perfdata = Vec::new();
+    let mut nearest: Option<(String, i64)> = None;
+    for (domain, res) in &domains {
+        let domain_state = match account_checker::domain_status(res, now, critical_days) {
+            DomainStatus::Error => PluginState::Unknown,
+            DomainStatus::Expired | DomainStatus::Expiring => PluginState::Critical,
+            DomainStatus::Disabled => PluginState::Ok,
+            DomainStatus::Ok => match account_checker::domain_status(res, now, warning_days) {
+                DomainStatus::Expiring => PluginState::Warning,
+                _ => PluginState::Ok,
+            },
+        };
+        state = state.worst(domain_state);
+
+        match res {
+            Err(err) => problems.push(format!("{}: {}", domain.display_name(), err)),
+            Ok(CheckDomainResult::ExpireDate(expire)) => {
+                let days = account_checker::days_left(expire, now);
+                // Range "N:" alerts if value < N, plugin alerts if days left <= threshold.
+                perfdata.push(format!(
+                    "days_left_{}={};{}:;{}:",
+                    domain.ascii_name(),
+                    days,
+                    warning_days + 1,
+                    critical_days + 1
+                ));
+                if domain_state != PluginState::Ok {
+                    if expire < now {
+                        problems.push(format!(
+                            "{} expired {} days ago",
+                            domain.display_name(),
+                            -days
+                        ));
+                    } else {
+                        problems.push(format!(
+                            "{} expires in {} days",
+                            domain.display_name(),
+                            days
+                        ));
+                    }
+                }
+                nearest = match nearest {
+                    Some((_, nearest_days)) if nearest_days <= days => nearest,
+                    _ => Some((domain.display_name(), days)),
+                };
+            }
+            Ok(CheckDomainResult::Disabled) => {}
+        }
+    }
+
+    let message = if problems.is_empty() {
+        match nearest {
+            Some((domain, days)) => format!(
+                "{} domains, nearest {} in {} days",
+                domains.len(),
+                domain,
+                days
+            ),
+            None => format!("{} domains", domains.len()),
+        }
+    } else {
+        problems.join(", ")
+    };
+    let mut line = format!("WHOIS {} - {}", state.as_str(), message);
+    if !perfdata.is_empty() {
+        line += " | ";
+        line += &perfdata.join(" ");
+    }
+    return (state, line);
+}
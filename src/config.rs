@@ -1,10 +1,24 @@
-use crate::errors::Error;
+use crate::{errors::Error, registrar::RegistrarAccountConfig, whois::WhoisServerConfig};
+use chrono_tz::Tz;
+use serde::Deserialize as _;
 use serde_derive::Deserialize;
+use std::collections::HashMap;
 
 type Result<T> = std::result::Result<T, Error>;
 
 pub(super) const DEFAULT_CONFIG_YAML: &str = include_str!("../config-default.yaml");
 
+// Prefix of environment variables, which override options of config file.
+const ENV_PREFIX: &str = "WHOIS_MONITORING_";
+
+// List options, which are comma separated in environment variables.
+const ENV_LIST_OPTIONS: &[&str] = &[
+    "admin_emails",
+    "customers_file",
+    "whois_source_ips",
+    "forecast_days",
+];
+
 #[derive(Debug, Deserialize)]
 pub(super) enum LogFormat {
     Hierarchy,
@@ -18,11 +32,19 @@ pub(super) enum LogLevel {
     Error,
 }
 
+#[derive(Debug, Deserialize)]
+pub(super) enum StateBackend {
+    Yaml,
+    Sqlite,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub(super) struct Config {
     pub admin_emails: Vec<String>,
 
+    pub admin_digest: bool,
+
     pub log_format: LogFormat,
     pub log_level: LogLevel,
 
@@ -33,7 +55,9 @@ pub(super) struct Config {
     pub smtp_tls: bool,
 
     pub smtp_login: String,
+    pub smtp_login_file: String,
     pub smtp_password: String,
+    pub smtp_password_file: String,
 
     pub smtp_from: String,
 
@@ -41,11 +65,54 @@ pub(super) struct Config {
 
     pub ok_report_day: u8,
 
+    pub timezone: Tz,
+
+    pub ics_attachment: bool,
+
     pub no_cache_days_before_expire: i64,
+    pub cache_alert_refresh_hours: u32,
+    pub cache_max_age_days: u32,
+    pub cache_jitter_hours: u32,
 
     pub state_file: String,
 
-    pub customers_file: String,
+    pub state_backend: StateBackend,
+    pub state_lock_wait_seconds: u32,
+    pub whois_archive_retention_days: u32,
+
+    // Files, directories or glob patterns. Single string is allowed too.
+    #[serde(deserialize_with = "string_or_list")]
+    pub customers_file: Vec<String>,
+
+    pub public_suffix_list: String,
+
+    // Zone or domain -> whois server.
+    pub whois_servers: HashMap<String, WhoisServerConfig>,
+
+    // socks5://[login:password@]host[:port] or http://[login:password@]host[:port].
+    pub whois_proxy: String,
+
+    // Source addresses of lookups, used in turn.
+    pub whois_source_ips: Vec<String>,
+
+    // Account field of domains -> registrar API credentials.
+    pub registrar_accounts: HashMap<String, RegistrarAccountConfig>,
+
+    // Zone -> renewal price in currency of account balance.
+    pub renewal_prices: HashMap<String, f64>,
+
+    // Periods of renewal forecast.
+    pub forecast_days: Vec<u32>,
+
+    pub admin_digest_forecast: bool,
+
+    pub daemon_check_schedule: String,
+    pub daemon_check_interval_minutes: u32,
+    pub daemon_report_time: String,
+
+    pub http_listen: String,
+
+    pub metrics_textfile: String,
 }
 
 impl Config {
@@ -57,13 +124,134 @@ impl Config {
     pub fn from_file(fname: &str) -> Result<Self> {
         let mut cfg = default_config();
         cfg.merge(config::File::with_name(fname))?;
-        return Ok(cfg.try_into()?);
+        merge_env(&mut cfg, std::env::vars())?;
+        let mut cfg: Config = cfg.try_into()?;
+        cfg.read_secret_files()?;
+        return Ok(cfg);
+    }
+
+    // Secrets from files override values of options.
+    fn read_secret_files(&mut self) -> Result<()> {
+        if !self.smtp_login_file.is_empty() {
+            self.smtp_login = read_secret_file(&self.smtp_login_file)?;
+        }
+        if !self.smtp_password_file.is_empty() {
+            self.smtp_password = read_secret_file(&self.smtp_password_file)?;
+        }
+        for account in self.registrar_accounts.values_mut() {
+            if !account.password_file.is_empty() {
+                account.password = read_secret_file(&account.password_file)?;
+            }
+            if !account.api_key_file.is_empty() {
+                account.api_key = read_secret_file(&account.api_key_file)?;
+            }
+            if !account.api_secret_file.is_empty() {
+                account.api_secret = read_secret_file(&account.api_secret_file)?;
+            }
+        }
+        return Ok(());
     }
 }
 
+// Content of file without trailing newline.
+fn read_secret_file(fname: &str) -> Result<String> {
+    let content = std::fs::read_to_string(fname).map_err(|err| {
+        ::config::ConfigError::Message(format!("Can't read secret file {}: {}", fname, err))
+    })?;
+    return Ok(content
+        .trim_end_matches(|c| c == '\n' || c == '\r')
+        .to_string());
+}
+
+fn string_or_list<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+
+    return match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(item) => Ok(vec![item]),
+        StringOrList::List(items) => Ok(items),
+    };
+}
+
+// Options from environment variables WHOIS_MONITORING_<OPTION>. Map options and variables
+// of unknown options are ignored: other software may use the prefix too.
+fn merge_env<I>(cfg: &mut ::config::Config, vars: I) -> Result<()>
+where
+    I: Iterator<Item = (String, String)>,
+{
+    let defaults = default_config();
+    for (name, value) in vars {
+        if !name.starts_with(ENV_PREFIX) {
+            continue;
+        }
+        let key = name[ENV_PREFIX.len()..].to_lowercase();
+        let is_map = match defaults.get::<::config::Value>(&key) {
+            Ok(default) => default.into_table().is_ok(),
+            Err(_) => continue,
+        };
+        if is_map {
+            continue;
+        }
+        if ENV_LIST_OPTIONS.contains(&key.as_str()) {
+            let items: Vec<String> = value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect();
+            cfg.set(&key, items)?;
+        } else {
+            cfg.set(&key, value)?;
+        }
+    }
+    return Ok(());
+}
+
 fn default_config() -> ::config::Config {
     let mut settings = ::config::Config::new();
     let config_file = config::File::from_str(DEFAULT_CONFIG_YAML, config::FileFormat::Yaml);
     settings.merge(config_file).unwrap();
     return settings;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_from_env() {
+        let mut settings = default_config();
+        let vars = vec![
+            ("WHOIS_MONITORING_SMTP_SERVER", "smtp.example.com"),
+            ("WHOIS_MONITORING_SMTP_PORT", "587"),
+            (
+                "WHOIS_MONITORING_ADMIN_EMAILS",
+                "a@example.com, b@example.com",
+            ),
+            ("WHOIS_MONITORING_FORECAST_DAYS", "10,20"),
+            ("WHOIS_MONITORING_CUSTOMERS_FILE", "customers.d"),
+            ("WHOIS_MONITORING_RENEWAL_PRICES", "com"),
+            ("WHOIS_MONITORING_VERSION", "1.0"),
+            ("SMTP_SERVER", "other.example.com"),
+        ];
+        merge_env(
+            &mut settings,
+            vars.into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string())),
+        )
+        .unwrap();
+        let cfg: Config = settings.try_into().unwrap();
+        assert_eq!(cfg.smtp_server, "smtp.example.com");
+        assert_eq!(cfg.smtp_port, 587);
+        assert_eq!(cfg.admin_emails, vec!["a@example.com", "b@example.com"]);
+        assert_eq!(cfg.forecast_days, vec![10, 20]);
+        assert_eq!(cfg.customers_file, vec!["customers.d"]);
+        assert!(cfg.renewal_prices.is_empty());
+    }
+}
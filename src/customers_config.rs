@@ -1,4 +1,14 @@
+use crate::{
+    errors::{Error, Result},
+    validation,
+    whois::WhoisServerConfig,
+};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Serialize, Deserialize, Hash, Eq, PartialEq)]
 pub(crate) struct CustomerConfig {
@@ -6,6 +16,11 @@ pub(crate) struct CustomerConfig {
 
     #[serde(default)]
     pub disabled: bool,
+
+    // Timezone for dates in reports. Config timezone used if empty.
+    #[serde(default)]
+    pub timezone: Option<Tz>,
+
     pub emails: Vec<String>,
     pub domains: Vec<DomainConfig>,
 }
@@ -14,12 +29,214 @@ pub(crate) struct CustomerConfig {
 pub(crate) struct DomainConfig {
     pub domain: String,
 
-    #[serde(default)]
-    pub account: String,
+    #[serde(default, deserialize_with = "string_or_account")]
+    pub account: AccountConfig,
 
     #[serde(default)]
     pub autorenew: bool,
 
     #[serde(default)]
     pub disabled: bool,
+
+    // Whois server of the domain, overrides whois_servers of config.
+    #[serde(default)]
+    pub whois_server: Option<WhoisServerConfig>,
+}
+
+// Registrar account of domain.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AccountConfig {
+    #[serde(default)]
+    pub registrar: String,
+
+    #[serde(default)]
+    pub login: String,
+
+    #[serde(default)]
+    pub contract: String,
+
+    // Key of registrar_accounts in config. Default: registrar/login.
+    #[serde(default)]
+    pub credentials_ref: String,
+}
+
+impl AccountConfig {
+    // Old free-form string: "registrar - login" or "registrar/login".
+    pub(crate) fn from_string(account: &str) -> Self {
+        let mut parts = if account.contains(" - ") {
+            account.splitn(2, " - ")
+        } else {
+            account.splitn(2, "/")
+        };
+        return AccountConfig {
+            registrar: parts.next().unwrap_or("").trim().to_string(),
+            login: parts.next().unwrap_or("").trim().to_string(),
+            ..AccountConfig::default()
+        };
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        return self.registrar.is_empty() && self.login.is_empty();
+    }
+
+    // Name of account for grouping and filters: registrar/login, lowercase.
+    // Registrars and logins are case insensitive.
+    pub(crate) fn key(&self) -> String {
+        if self.login.is_empty() {
+            return self.registrar.to_lowercase();
+        }
+        return format!(
+            "{}/{}",
+            self.registrar.to_lowercase(),
+            self.login.to_lowercase()
+        );
+    }
+
+    // Key of registrar API credentials. Lowercase: config lowercases keys of registrar_accounts.
+    pub(crate) fn credentials_key(&self) -> String {
+        if self.credentials_ref.is_empty() {
+            return self.key();
+        }
+        return self.credentials_ref.to_lowercase();
+    }
+}
+
+impl fmt::Display for AccountConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.key())?;
+        if !self.contract.is_empty() {
+            write!(f, " ({})", self.contract)?;
+        }
+        return Ok(());
+    }
+}
+
+fn string_or_account<'de, D>(deserializer: D) -> std::result::Result<AccountConfig, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrAccount {
+        String(String),
+        Account(AccountConfig),
+    }
+    return match StringOrAccount::deserialize(deserializer)? {
+        StringOrAccount::String(account) => Ok(AccountConfig::from_string(&account)),
+        StringOrAccount::Account(account) => Ok(account),
+    };
+}
+
+impl DomainConfig {
+    // Punycode name for whois lookups and cache keys.
+    pub(crate) fn ascii_name(&self) -> String {
+        return ascii_domain(&self.domain);
+    }
+
+    // Unicode name for reports.
+    pub(crate) fn display_name(&self) -> String {
+        return unicode_domain(&self.domain);
+    }
+}
+
+// Normalized punycode name or lowercase name, if domain is invalid.
+pub(crate) fn ascii_domain(domain: &str) -> String {
+    return normalize_domain(domain).unwrap_or_else(|_| domain.to_lowercase());
+}
+
+pub(crate) fn unicode_domain(domain: &str) -> String {
+    return idna::domain_to_unicode(&ascii_domain(domain)).0;
+}
+
+// Customers of one file.
+pub(crate) struct CustomersFile {
+    pub path: PathBuf,
+    pub customers: Vec<CustomerConfig>,
+}
+
+// Load and merge customers from files, directories and glob patterns.
+pub(crate) fn load_customers(paths: &[String]) -> Result<Vec<CustomerConfig>> {
+    let files = load_customers_files(paths)?;
+    let problems = validation::find_duplicates(&files);
+    if !problems.is_empty() {
+        let problems = problems.iter().map(|problem| problem.to_string()).collect();
+        return Err(Error::CustomersValidationError(problems));
+    }
+    return Ok(files.into_iter().flat_map(|file| file.customers).collect());
+}
+
+pub(crate) fn load_customers_files(paths: &[String]) -> Result<Vec<CustomersFile>> {
+    let mut res = Vec::new();
+    for path in resolve_paths(paths)? {
+        let f = fs::File::open(&path)?;
+        let customers = serde_yaml::from_reader(f)
+            .map_err(|err| Error::CustomersFileError(path.to_string_lossy().to_string(), err))?;
+        res.push(CustomersFile { path, customers });
+    }
+    return Ok(res);
+}
+
+// Files in order of paths. Files of directory and glob are sorted by name.
+fn resolve_paths(paths: &[String]) -> Result<Vec<PathBuf>> {
+    let mut res = Vec::new();
+    for path in paths {
+        let mut files = Vec::new();
+        if path.contains(|c| c == '*' || c == '?' || c == '[') {
+            let pattern = glob::glob(path)
+                .map_err(|err| Error::CustomersPathError(format!("{}: {}", path, err)))?;
+            for item in pattern {
+                let item =
+                    item.map_err(|err| Error::CustomersPathError(format!("{}: {}", path, err)))?;
+                if item.is_file() {
+                    files.push(item);
+                }
+            }
+        } else if Path::new(path).is_dir() {
+            for item in fs::read_dir(path)? {
+                let item = item?.path();
+                let is_yaml = match item.extension().and_then(|ext| ext.to_str()) {
+                    Some("yaml") | Some("yml") => true,
+                    _ => false,
+                };
+                if is_yaml && item.is_file() {
+                    files.push(item);
+                }
+            }
+        } else {
+            res.push(PathBuf::from(path));
+            continue;
+        }
+        files.sort();
+        res.extend(files);
+    }
+    return Ok(res);
+}
+
+// Canonical domain name: lowercase punycode without trailing dot.
+pub(crate) fn normalize_domain(domain: &str) -> std::result::Result<String, String> {
+    let domain = domain.trim().trim_end_matches('.');
+    if domain.is_empty() {
+        return Err("empty domain name".to_string());
+    }
+    let ascii = idna::domain_to_ascii(domain)
+        .map_err(|err| format!("bad domain name {}: {:?}", domain, err))?;
+    if ascii.len() > 253 {
+        return Err(format!("domain name {} is too long", domain));
+    }
+    let labels: Vec<&str> = ascii.split('.').collect();
+    if labels.len() < 2 {
+        return Err(format!("domain name {} has no zone", domain));
+    }
+    for label in labels {
+        let is_valid = !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !is_valid {
+            return Err(format!("bad label '{}' in domain name {}", label, domain));
+        }
+    }
+    return Ok(ascii);
 }
@@ -0,0 +1,162 @@
+use {
+    crate::{
+        account_checker::AccountChecker,
+        cache::CachePolicy,
+        config::Config,
+        customers_config::{ascii_domain, CustomerConfig},
+        errors::Result,
+        export, http_server,
+        metrics::Metrics,
+        storage::Storage,
+    },
+    chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc},
+    chrono_tz::Tz,
+    slog::{debug, info, Level},
+    slog_unwraps::ResultExt,
+    std::{
+        str::FromStr,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc, Arc, Mutex,
+        },
+    },
+};
+
+// How often daemon wake up for check terminate flag and schedule, if no refresh requests.
+const TICK: std::time::Duration = std::time::Duration::from_secs(1);
+
+enum Schedule {
+    Cron(cron::Schedule),
+    Interval(Duration),
+}
+
+impl Schedule {
+    fn from_config(cfg: &Config) -> Result<Self> {
+        if cfg.daemon_check_schedule.is_empty() {
+            return Ok(Schedule::Interval(Duration::minutes(
+                cfg.daemon_check_interval_minutes as i64,
+            )));
+        }
+        return Ok(Schedule::Cron(cron::Schedule::from_str(
+            &cfg.daemon_check_schedule,
+        )?));
+    }
+
+    fn next_after(&self, time: &DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Schedule::Interval(interval) => *time + *interval,
+            Schedule::Cron(schedule) => schedule
+                .after(time)
+                .next()
+                .unwrap_or_else(|| *time + Duration::days(1)),
+        }
+    }
+}
+
+// Next moment after time, when local time in tz equal to report_time.
+fn next_report_time(tz: Tz, report_time: &NaiveTime, time: &DateTime<Utc>) -> DateTime<Utc> {
+    let mut date = time.with_timezone(&tz).date().naive_local();
+    loop {
+        if let Some(local) = tz
+            .from_local_datetime(&date.and_time(*report_time))
+            .earliest()
+        {
+            let res = local.with_timezone(&Utc);
+            if res > *time {
+                return res;
+            }
+        }
+        date = date.succ();
+    }
+}
+
+pub(crate) fn run_daemon(
+    cfg: &Config,
+    log: &slog::Logger,
+    checker: &mut AccountChecker,
+    storage: &mut Storage,
+    customers: &Vec<CustomerConfig>,
+) -> Result<()> {
+    let terminate = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::SIGTERM, Arc::clone(&terminate))?;
+    signal_hook::flag::register(signal_hook::SIGINT, Arc::clone(&terminate))?;
+
+    let schedule = Schedule::from_config(cfg)?;
+    let report_time = NaiveTime::parse_from_str(&cfg.daemon_report_time, "%H:%M")?;
+
+    let mut metrics = Metrics::new();
+    let state = Arc::new(Mutex::new(http_server::State::new()));
+    let (refresh_sender, refresh_receiver) = mpsc::channel::<http_server::RefreshRequest>();
+    if !cfg.http_listen.is_empty() {
+        http_server::start(log, &cfg.http_listen, Arc::clone(&state), refresh_sender)?;
+    }
+
+    let now = Utc::now();
+    let mut next_check = now;
+    let mut next_report = next_report_time(cfg.timezone, &report_time, &now);
+    info!(log, "Daemon started"; "next-report"=>next_report.to_rfc3339());
+
+    while !terminate.load(Ordering::SeqCst) {
+        let now = Utc::now();
+        let mut refresh_request = None;
+        let is_report = now >= next_report;
+        if is_report {
+            info!(log, "Start check and send reports");
+            next_report = next_report_time(cfg.timezone, &report_time, &now);
+            next_check = schedule.next_after(&now);
+        } else if now >= next_check {
+            info!(log, "Start check");
+            next_check = schedule.next_after(&now);
+        } else {
+            match refresh_receiver.recv_timeout(TICK) {
+                Ok(request) => {
+                    let is_monitored = customers.iter().any(|customer| {
+                        customer
+                            .domains
+                            .iter()
+                            .any(|domain| domain.ascii_name() == ascii_domain(&request.domain))
+                    });
+                    if !is_monitored {
+                        let _ = request.response.send(false);
+                        continue;
+                    }
+                    info!(log, "Refresh domain"; "domain"=>&request.domain);
+                    checker.invalidate(&request.domain);
+                    refresh_request = Some(request);
+                }
+                Err(_) => continue,
+            }
+        }
+
+        checker.clean_cache(&now, &CachePolicy::from_config(cfg));
+        let results = crate::check_customers(log, checker, customers);
+        let results: Vec<_> = results
+            .iter()
+            .map(|(customer, res)| (*customer, res))
+            .collect();
+        metrics.update_domains(&results, &checker.stats);
+        if is_report {
+            let notifications = crate::send_reports(&now, cfg, log, &results);
+            metrics.emails_sent(&notifications);
+            let _ = storage
+                .save_notifications(&notifications)
+                .log(log, Level::Error);
+        }
+        {
+            let mut state = state.lock().unwrap();
+            state.metrics = metrics.clone();
+            state.records = export::create_records(cfg, &results, &now);
+            state.updated = Some(now);
+        }
+        if let Some(request) = refresh_request {
+            let _ = request.response.send(true);
+        }
+
+        let _ = storage.save(checker).log(log, Level::Error);
+        debug!(log, "Wait next run";
+            "next-check"=>next_check.to_rfc3339(), "next-report"=>next_report.to_rfc3339()
+        );
+    }
+    info!(log, "Daemon stopped");
+    return Ok(());
+}
@@ -4,14 +4,30 @@ pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
 pub enum Error {
-    WhoisError(whois2::Error),
+    WhoisError(String),
     IoError(std::io::Error),
     SerdeError(serde_yaml::Error),
     ChronoFormatParseError(chrono::ParseError),
     CanFindWhoisField,
+    CustomerNotFound(String),
     ConfigError(::config::ConfigError),
     LettreEmailError(lettre_email::error::Error),
     LettreSmtpError(lettre::smtp::error::Error),
+    CsvError(csv::Error),
+    CronError(cron::error::Error),
+    HttpServerError(String),
+    SqliteError(rusqlite::Error),
+    NeedSqliteStorage,
+    StateLocked(String),
+    StateVersionError(String),
+    ArchiveDisabled,
+    CustomersFileError(String, serde_yaml::Error),
+    CustomersPathError(String),
+    CustomersValidationError(Vec<String>),
+    ConfigInvalid(usize),
+    NetError(String),
+    RegistrarError(String),
+    ImportError(String),
 }
 
 use Error::*;
@@ -20,13 +36,33 @@ impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ChronoFormatParseError(err) => Display::fmt(err, f),
-            WhoisError(err) => Display::fmt(err, f),
+            WhoisError(err) => f.write_str(err),
             IoError(err) => Display::fmt(err, f),
             SerdeError(err) => Display::fmt(err, f),
             CanFindWhoisField => f.write_str("Can't find whois field"),
+            CustomerNotFound(name) => write!(f, "Customer not found: {}", name),
             ConfigError(err) => Display::fmt(err, f),
             LettreEmailError(err) => Display::fmt(err, f),
             LettreSmtpError(err) => Display::fmt(err, f),
+            CsvError(err) => Display::fmt(err, f),
+            CronError(err) => Display::fmt(err, f),
+            HttpServerError(err) => write!(f, "Http server error: {}", err),
+            SqliteError(err) => Display::fmt(err, f),
+            NeedSqliteStorage => f.write_str("The command works with sqlite state backend only"),
+            StateVersionError(err) => write!(f, "Unsupported state: {}", err),
+            CustomersFileError(fname, err) => write!(f, "{}: {}", fname, err),
+            CustomersPathError(err) => write!(f, "Bad customers path: {}", err),
+            CustomersValidationError(problems) => {
+                write!(f, "Customers config is invalid:\n{}", problems.join("\n"))
+            }
+            ConfigInvalid(count) => write!(f, "Config has {} problems", count),
+            NetError(err) => f.write_str(err),
+            RegistrarError(err) => f.write_str(err),
+            ImportError(err) => write!(f, "Import error: {}", err),
+            ArchiveDisabled => f.write_str(
+                "Whois archive is disabled: state_file is empty or whois_archive_retention_days is 0",
+            ),
+            StateLocked(fname) => write!(f, "State is locked by other instance: {}", fname),
         }
     }
 }
@@ -67,8 +103,20 @@ impl From<lettre_email::error::Error> for Error {
     }
 }
 
-impl From<whois2::Error> for Error {
-    fn from(err: whois2::Error) -> Error {
-        WhoisError(err)
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Error {
+        CsvError(err)
+    }
+}
+
+impl From<cron::error::Error> for Error {
+    fn from(err: cron::error::Error) -> Error {
+        CronError(err)
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        SqliteError(err)
     }
 }
@@ -0,0 +1,127 @@
+use {
+    crate::{
+        account_checker::{self, CheckAccountResult, CheckDomainResult},
+        config::Config,
+        customers_config::CustomerConfig,
+        errors::Result,
+    },
+    chrono::{DateTime, Utc},
+    serde::Serialize,
+    std::{io, str::FromStr},
+};
+
+#[derive(Debug)]
+pub(crate) enum Format {
+    Json,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("Unknown export format: {}. Use json or csv.", s)),
+        }
+    }
+}
+
+// One row of export: flat description of domain check result.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DomainRecord {
+    pub customer: String,
+    pub domain: String,
+    pub account: String,
+    pub registrar: String,
+    pub login: String,
+    pub expire: Option<String>,
+    pub days_left: Option<i64>,
+    pub autorenew: bool,
+    pub status: &'static str,
+    pub error: Option<String>,
+}
+
+impl DomainRecord {
+    pub(crate) fn to_json(&self) -> json::JsonValue {
+        return json::object! {
+            "customer" => self.customer.clone(),
+            "domain" => self.domain.clone(),
+            "account" => self.account.clone(),
+            "registrar" => self.registrar.clone(),
+            "login" => self.login.clone(),
+            "expire" => self.expire.clone(),
+            "days_left" => self.days_left,
+            "autorenew" => self.autorenew,
+            "status" => self.status,
+            "error" => self.error.clone(),
+        };
+    }
+}
+
+pub(crate) fn create_records(
+    cfg: &Config,
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+    now: &DateTime<Utc>,
+) -> Vec<DomainRecord> {
+    let mut res = Vec::new();
+    for (customer, check_result) in results {
+        for (domain, domain_result) in check_result.domain_results() {
+            let (expire, days_left, error) = match domain_result {
+                Ok(CheckDomainResult::ExpireDate(expire)) => (
+                    Some(expire.to_rfc3339()),
+                    Some(account_checker::days_left(expire, now)),
+                    None,
+                ),
+                Ok(CheckDomainResult::Disabled) => (None, None, None),
+                Err(err) => (None, None, Some(err.to_string())),
+            };
+            res.push(DomainRecord {
+                customer: customer.name.clone(),
+                domain: domain.display_name(),
+                account: domain.account.to_string(),
+                registrar: domain.account.registrar.clone(),
+                login: domain.account.login.clone(),
+                expire,
+                days_left,
+                autorenew: check_result.autorenew(domain),
+                status: account_checker::domain_status(
+                    domain_result,
+                    now,
+                    cfg.expire_soon_days as i64,
+                )
+                .as_str(),
+                error,
+            });
+        }
+    }
+    res.sort_by(|r1, r2| {
+        r1.customer
+            .cmp(&r2.customer)
+            .then_with(|| r1.domain.cmp(&r2.domain))
+    });
+    return res;
+}
+
+pub(crate) fn write_records<W: io::Write>(
+    mut writer: W,
+    format: &Format,
+    records: &[DomainRecord],
+) -> Result<()> {
+    match format {
+        Format::Json => {
+            let items: Vec<json::JsonValue> = records.iter().map(|r| r.to_json()).collect();
+            json::JsonValue::Array(items).write_pretty(&mut writer, 2)?;
+            writer.write_all(b"\n")?;
+        }
+        Format::Csv => {
+            let mut csv_writer = csv::Writer::from_writer(writer);
+            for record in records {
+                csv_writer.serialize(record)?;
+            }
+            csv_writer.flush()?;
+        }
+    }
+    return Ok(());
+}
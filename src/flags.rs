@@ -1,3 +1,4 @@
+use crate::export;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -15,6 +16,149 @@ pub(super) struct Flags {
     #[structopt(long = "print-customers-example")]
     /// Print customers.yaml example and exit.
     pub print_customers_example: bool,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+pub(super) enum Command {
+    #[structopt(name = "run")]
+    /// Check domains of all customers and send reports. Default command.
+    Run,
+
+    #[structopt(name = "daemon")]
+    /// Work in background: check domains by schedule and send reports at daemon_report_time.
+    Daemon,
+
+    #[structopt(name = "check")]
+    /// Lookup domains without cache and print parsed result and raw whois.
+    Check {
+        #[structopt(raw(required = "true"))]
+        /// Domains for lookup
+        domains: Vec<String>,
+    },
+
+    #[structopt(name = "check-plugin")]
+    /// Nagios/Icinga compatible check. Exit code: 0 - OK, 1 - WARNING, 2 - CRITICAL, 3 - UNKNOWN.
+    /// Check domains of all customers if no customers and domains given.
+    CheckPlugin {
+        #[structopt(long = "customer")]
+        /// Check domains of the customer. Can be repeated.
+        customers: Vec<String>,
+
+        #[structopt(long = "domain")]
+        /// Check the domain. Can be repeated.
+        domains: Vec<String>,
+
+        #[structopt(short = "w", long = "warning", default_value = "30")]
+        /// Warning if domain expire in the days or less
+        warning: i64,
+
+        #[structopt(short = "c", long = "critical", default_value = "7")]
+        /// Critical if domain expire in the days or less
+        critical: i64,
+    },
+
+    #[structopt(name = "report")]
+    /// Check domains of the customer and print report without send emails.
+    Report {
+        /// Customer name
+        customer: String,
+    },
+
+    #[structopt(name = "cache")]
+    /// Manage state cache.
+    Cache {
+        #[structopt(subcommand)]
+        cmd: CacheCommand,
+    },
+
+    #[structopt(name = "history")]
+    /// Print history of whois lookups of the domain. Works with sqlite state backend only.
+    History {
+        /// Domain name
+        domain: String,
+    },
+
+    #[structopt(name = "archive")]
+    /// Print last raw whois responses of the domain from archive.
+    Archive {
+        /// Domain name
+        domain: String,
+
+        /// Count of responses
+        #[structopt(short = "n", long = "count", default_value = "2")]
+        count: usize,
+
+        /// Print changes between responses instead of full responses
+        #[structopt(long = "diff")]
+        diff: bool,
+    },
+
+    #[structopt(name = "config")]
+    /// Config tools.
+    Config {
+        #[structopt(subcommand)]
+        cmd: ConfigCommand,
+    },
+
+    #[structopt(name = "calendar")]
+    /// Check domains of all customers and write iCalendar file with renew deadlines.
+    Calendar {
+        #[structopt(short = "o", long = "output", default_value = "domains.ics")]
+        /// Path to calendar file
+        output: String,
+    },
+
+    #[structopt(name = "export")]
+    /// Check domains of all customers and export results in machine-readable format.
+    Export {
+        #[structopt(short = "f", long = "format", default_value = "json")]
+        /// Export format: json or csv
+        format: export::Format,
+
+        #[structopt(short = "o", long = "output")]
+        /// Path to output file. Write to stdout if empty.
+        output: Option<String>,
+    },
+
+    #[structopt(name = "accounts")]
+    /// Check domains of all customers and print report grouped by registrar accounts.
+    Accounts {
+        #[structopt(long = "account")]
+        /// Show the account only: registrar/login, for example reg.ru/qweqwe. Can be repeated.
+        accounts: Vec<String>,
+
+        #[structopt(long = "expiring")]
+        /// Show domains, which expire in the days or less
+        expiring: Option<i64>,
+    },
+
+    #[structopt(name = "forecast")]
+    /// Check domains of all customers and print forecast of renewal costs by registrar accounts.
+    Forecast,
+
+    #[structopt(name = "import")]
+    /// Compare domains of registrar account with customers: print domains, which aren't monitored,
+    /// and domains of the account in customers, which aren't in the account.
+    Import {
+        /// Registrar account: registrar/login, for example reg.ru/qweqwe
+        account: String,
+
+        #[structopt(short = "f", long = "file")]
+        /// Path to CSV export of the account (reg.ru, nic.ru, Namecheap).
+        /// Domains are read from registrar API if empty.
+        file: Option<String>,
+
+        #[structopt(short = "c", long = "column")]
+        /// Header of domain column in CSV export, detected by known headers if empty.
+        column: Option<String>,
+    },
+
+    #[structopt(name = "balance")]
+    /// Print balance of registrar accounts from registrar APIs.
+    Balance,
 }
 
 impl Flags {
@@ -22,3 +166,55 @@ impl Flags {
         Flags::from_args()
     }
 }
+
+#[derive(StructOpt, Debug)]
+pub(super) enum CacheCommand {
+    #[structopt(name = "show")]
+    /// Print cached expire dates.
+    Show,
+
+    #[structopt(name = "clear")]
+    /// Remove all domains from cache.
+    Clear,
+
+    #[structopt(name = "invalidate")]
+    /// Remove domains from cache.
+    Invalidate {
+        #[structopt(raw(required = "true"))]
+        /// Domains for remove from cache
+        domains: Vec<String>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub(super) enum ConfigCommand {
+    #[structopt(name = "validate")]
+    /// Load config and customers file and check them.
+    Validate,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_plugin_thresholds() {
+        let flags = Flags::from_iter(&[
+            "whois-monitoring",
+            "-c",
+            "my.yaml",
+            "check-plugin",
+            "-w",
+            "20",
+            "-c",
+            "5",
+        ]);
+        assert_eq!(flags.config, "my.yaml");
+        match flags.command {
+            Some(Command::CheckPlugin {
+                warning, critical, ..
+            }) => assert_eq!((warning, critical), (20, 5)),
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use {
+    crate::{
+        account_checker::{self, CheckAccountResult, CheckDomainResult},
+        config::Config,
+        customers_config::{unicode_domain, AccountConfig, CustomerConfig},
+        errors::Result,
+        public_suffix::SuffixList,
+        registrar::{self, Balance, Registrars},
+    },
+    chrono::{DateTime, Utc},
+    slog::Level,
+    slog_unwraps::ResultExt,
+    std::collections::{BTreeMap, HashMap},
+};
+
+// Registration, which expires within forecast period or with unknown expire date.
+struct ForecastDomain {
+    // Registrable ascii name: subdomains of registration are renewed with it.
+    name: String,
+    domain: String,
+    customers: Vec<String>,
+
+    // None - lookup of expire date failed.
+    days_left: Option<i64>,
+
+    // None - no price for zone of domain.
+    price: Option<f64>,
+}
+
+// Expiring domains of registrar account and balance.
+struct AccountForecast {
+    account: AccountConfig,
+    domains: Vec<ForecastDomain>,
+
+    // None - account without registrar API or API doesn't report balance.
+    balance: Option<Result<Balance>>,
+}
+
+// Renewal cost of domains, which expire in the days or less.
+struct PeriodCost {
+    days: i64,
+    count: usize,
+    cost: f64,
+
+    // Domains without price.
+    unknown: usize,
+
+    // Domains with failed lookup, they can expire in the period.
+    unknown_expiry: usize,
+}
+
+impl AccountForecast {
+    fn period_cost(&self, days: i64) -> PeriodCost {
+        let mut res = PeriodCost {
+            days,
+            count: 0,
+            cost: 0.0,
+            unknown: 0,
+            unknown_expiry: 0,
+        };
+        for domain in &self.domains {
+            match domain.days_left {
+                Some(days_left) if days_left <= days => {}
+                Some(_) => continue,
+                None => {
+                    res.unknown_expiry += 1;
+                    continue;
+                }
+            }
+            res.count += 1;
+            match domain.price {
+                Some(price) => res.cost += price,
+                None => res.unknown += 1,
+            }
+        }
+        return res;
+    }
+}
+
+// Zones of prices in punycode, like domain names.
+fn ascii_prices(prices: &HashMap<String, f64>) -> HashMap<String, f64> {
+    return prices
+        .iter()
+        .map(|(zone, price)| {
+            let zone = zone.trim().trim_matches('.');
+            let ascii = idna::domain_to_ascii(zone).unwrap_or_else(|_| zone.to_lowercase());
+            return (ascii, *price);
+        })
+        .collect();
+}
+
+// Renewal price of ascii domain name: price of longest matched zone.
+// Prices of registrar account override prices from config.
+fn renewal_price(
+    domain: &str,
+    prices: &HashMap<String, f64>,
+    account_prices: Option<&HashMap<String, f64>>,
+) -> Option<f64> {
+    let mut zone = domain;
+    while let Some(index) = zone.find('.') {
+        zone = &zone[index + 1..];
+        if let Some(price) = account_prices.and_then(|prices| prices.get(zone)) {
+            return Some(*price);
+        }
+        if let Some(price) = prices.get(zone) {
+            return Some(*price);
+        }
+    }
+    return None;
+}
+
+// Domains of all customers, which expire within longest forecast period or with failed lookup,
+// grouped by account. Registration of several customers or subdomains is counted once.
+fn create_forecasts(
+    cfg: &Config,
+    log: &slog::Logger,
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+    now: &DateTime<Utc>,
+) -> Vec<AccountForecast> {
+    let registrars = Registrars::from_config(cfg);
+    let suffix_list = SuffixList::from_config(log, cfg);
+    let prices = ascii_prices(&cfg.renewal_prices);
+    let max_days = cfg.forecast_days.iter().cloned().max().unwrap_or(0) as i64;
+    let mut groups: BTreeMap<String, AccountForecast> = BTreeMap::new();
+    for (customer, check_result) in results {
+        for (domain, domain_result) in check_result.domain_results() {
+            let days_left = match domain_result {
+                Ok(CheckDomainResult::ExpireDate(expire)) => {
+                    let days_left = account_checker::days_left(expire, now);
+                    if days_left > max_days {
+                        continue;
+                    }
+                    Some(days_left)
+                }
+                Ok(CheckDomainResult::Disabled) => continue,
+                Err(_) => None,
+            };
+            let name = suffix_list.registrable_domain(&domain.ascii_name());
+            let forecast =
+                groups
+                    .entry(domain.account.to_string())
+                    .or_insert_with(|| AccountForecast {
+                        account: domain.account.clone(),
+                        domains: Vec::new(),
+                        balance: None,
+                    });
+            if let Some(item) = forecast.domains.iter_mut().find(|item| item.name == name) {
+                if !item.customers.contains(&customer.name) {
+                    item.customers.push(customer.name.clone());
+                }
+                continue;
+            }
+            let registrar_account = registrars.account(&domain.account);
+            let account_prices = registrar_account
+                .as_ref()
+                .map(|account| ascii_prices(&account.config.renewal_prices));
+            let price = renewal_price(&name, &prices, account_prices.as_ref());
+            forecast.domains.push(ForecastDomain {
+                domain: unicode_domain(&name),
+                name,
+                customers: vec![customer.name.clone()],
+                days_left,
+                price,
+            });
+        }
+    }
+    let mut res: Vec<AccountForecast> = groups.into_iter().map(|(_, item)| item).collect();
+    for forecast in &mut res {
+        forecast.domains.sort_by(|d1, d2| {
+            d1.days_left
+                .cmp(&d2.days_left)
+                .then_with(|| d1.domain.cmp(&d2.domain))
+        });
+        if let Some(account) = registrars.account(&forecast.account) {
+            forecast.balance = registrar::balance(&account)
+                .log(log, Level::Error)
+                .transpose();
+        }
+    }
+    return res;
+}
+
+// Forecast of renewal costs for forecast_days periods, compared with balance of accounts.
+pub(crate) fn create_report(
+    cfg: &Config,
+    log: &slog::Logger,
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+    now: &DateTime<Utc>,
+) -> String {
+    let mut periods: Vec<i64> = cfg.forecast_days.iter().map(|days| *days as i64).collect();
+    periods.sort();
+    let mut res = String::new();
+    for forecast in create_forecasts(cfg, log, results, now) {
+        let account = if forecast.account.is_empty() {
+            "-".to_string()
+        } else {
+            forecast.account.to_string()
+        };
+        res += &format!("Account: {}\n", account);
+        let balance = match &forecast.balance {
+            Some(Ok(balance)) => {
+                res += &format!("Balance: {:.2} {}\n", balance.amount, balance.currency);
+                Some(balance.amount)
+            }
+            Some(Err(err)) => {
+                res += &format!("Balance: {}\n", err);
+                None
+            }
+            None => None,
+        };
+        for days in &periods {
+            let period = forecast.period_cost(*days);
+            res += &format!(
+                "{} days: {} domains, cost {:.2}",
+                period.days, period.count, period.cost
+            );
+            if period.unknown > 0 {
+                res += &format!(" + {} domains without price", period.unknown);
+            }
+            if period.unknown_expiry > 0 {
+                res += &format!(" + {} domains with unknown expiry", period.unknown_expiry);
+            }
+            if let Some(balance) = balance {
+                if period.cost > balance {
+                    res += &format!(
+                        ", NOT ENOUGH BALANCE: need {:.2} more",
+                        period.cost - balance
+                    );
+                }
+            }
+            res += "\n";
+        }
+
+        let table: Vec<_> = forecast
+            .domains
+            .iter()
+            .map(|item| {
+                [
+                    item.domain.clone(),
+                    item.customers.join(", "),
+                    item.days_left
+                        .map(|days| days.to_string())
+                        .unwrap_or_else(|| "unknown expiry".to_string()),
+                    item.price
+                        .map(|price| format!("{:.2}", price))
+                        .unwrap_or_else(|| "unknown".to_string()),
+                ]
+            })
+            .collect();
+        let mut table_config = ascii_table::TableConfig::default();
+        table_config.width = 140;
+        for (index, header) in ["Domain", "Customers", "Days left", "Price"]
+            .iter()
+            .enumerate()
+        {
+            let mut column = ascii_table::ColumnConfig::default();
+            column.header = header.to_string();
+            table_config.columns.insert(index, column);
+        }
+        res += &ascii_table::format_table(&table, &table_config);
+        res += "\n";
+    }
+    return res;
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::errors::Error};
+
+    fn date(date: &str) -> DateTime<Utc> {
+        return DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc);
+    }
+
+    fn prices(items: &[(&str, f64)]) -> HashMap<String, f64> {
+        return items
+            .iter()
+            .map(|(zone, price)| (zone.to_string(), *price))
+            .collect();
+    }
+
+    #[test]
+    fn price_of_longest_zone() {
+        let config_prices = ascii_prices(&prices(&[("ru", 5.0), ("com.ru", 3.0), (".рф", 7.0)]));
+        let account_prices = ascii_prices(&prices(&[("ru", 4.0)]));
+        let price = |domain: &str| renewal_price(domain, &config_prices, Some(&account_prices));
+        assert_eq!(price("example.ru"), Some(4.0));
+        assert_eq!(price("example.com.ru"), Some(3.0));
+        assert_eq!(price("xn--e1afmkfd.xn--p1ai"), Some(7.0));
+        assert_eq!(price("example.org"), None);
+        assert_eq!(renewal_price("example.ru", &config_prices, None), Some(5.0));
+    }
+
+    #[test]
+    fn cost_of_periods() {
+        let domain = |days_left: Option<i64>, price: Option<f64>| ForecastDomain {
+            name: String::new(),
+            domain: String::new(),
+            customers: Vec::new(),
+            days_left,
+            price,
+        };
+        let forecast = AccountForecast {
+            account: AccountConfig::default(),
+            domains: vec![
+                domain(None, Some(1.0)),
+                domain(Some(-5), Some(10.0)),
+                domain(Some(30), Some(20.0)),
+                domain(Some(31), None),
+                domain(Some(90), Some(40.0)),
+            ],
+            balance: None,
+        };
+        let period = forecast.period_cost(30);
+        assert_eq!(
+            (
+                period.count,
+                period.cost,
+                period.unknown,
+                period.unknown_expiry
+            ),
+            (2, 30.0, 0, 1)
+        );
+        let period = forecast.period_cost(60);
+        assert_eq!(
+            (
+                period.count,
+                period.cost,
+                period.unknown,
+                period.unknown_expiry
+            ),
+            (3, 30.0, 1, 1)
+        );
+    }
+
+    #[test]
+    fn shared_registrations_and_failed_lookups() {
+        let customers: Vec<CustomerConfig> = serde_yaml::from_str(
+            "
+- name: c1
+  emails: []
+  domains:
+    - domain: www.example.com
+      account: reg.ru/Login
+    - domain: example.com
+      account: reg.ru/login
+    - domain: soon.ru
+      account: reg.ru/login
+    - domain: far.ru
+      account: reg.ru/login
+- name: c2
+  emails: []
+  domains:
+    - domain: example.com
+      account: REG.RU/LOGIN
+    - domain: failed.ru
+      account: reg.ru/login
+",
+        )
+        .unwrap();
+        let now = date("2026-01-01T00:00:00Z");
+        let expire = |days: i64| {
+            Ok(CheckDomainResult::ExpireDate(
+                now + chrono::Duration::days(days),
+            ))
+        };
+        let (c1, c2) = (&customers[0], &customers[1]);
+        let result1 = CheckAccountResult::from_results(
+            vec![
+                (&c1.domains[0], expire(10)),
+                (&c1.domains[1], expire(10)),
+                (&c1.domains[2], expire(40)),
+                (&c1.domains[3], expire(200)),
+            ],
+            Vec::new(),
+        );
+        let result2 = CheckAccountResult::from_results(
+            vec![
+                (&c2.domains[0], expire(10)),
+                (&c2.domains[1], Err(Error::CanFindWhoisField)),
+            ],
+            Vec::new(),
+        );
+        let suffix_list =
+            std::env::temp_dir().join(format!("forecast-psl-{}.dat", std::process::id()));
+        std::fs::write(&suffix_list, "// ===BEGIN ICANN DOMAINS===\ncom\nru\n").unwrap();
+        let mut cfg = Config::default();
+        cfg.public_suffix_list = suffix_list.to_string_lossy().to_string();
+        cfg.forecast_days = vec![60, 30];
+        cfg.renewal_prices = prices(&[("com", 10.0), ("ru", 5.0)]);
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+
+        let report = create_report(&cfg, &log, &[(c1, &result1), (c2, &result2)], &now);
+        std::fs::remove_file(&suffix_list).unwrap();
+        assert_eq!(report.matches("Account:").count(), 1, "{}", report);
+        assert!(report.contains("Account: reg.ru/login\n"), "{}", report);
+        assert!(
+            report.contains("30 days: 1 domains, cost 10.00 + 1 domains with unknown expiry\n"),
+            "{}",
+            report
+        );
+        assert!(
+            report.contains("60 days: 2 domains, cost 15.00 + 1 domains with unknown expiry\n"),
+            "{}",
+            report
+        );
+        assert!(report.contains("c1, c2"), "{}", report);
+        assert!(report.contains("unknown expiry │"), "{}", report);
+        assert!(!report.contains("far.ru"), "{}", report);
+    }
+}
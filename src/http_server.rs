@@ -0,0 +1,338 @@
+use {
+    crate::{
+        customers_config::ascii_domain,
+        errors::{Error, Result},
+        export::DomainRecord,
+        metrics::Metrics,
+    },
+    chrono::{DateTime, Utc},
+    slog::{debug, error, info, o},
+    std::{
+        collections::BTreeMap,
+        str::FromStr,
+        sync::{mpsc, Arc, Mutex},
+        thread,
+        time::Duration,
+    },
+    tiny_http::{Header, Method, Request, Response, Server},
+    url::percent_encoding::percent_decode,
+};
+
+// Max time for wait refresh of domain by daemon.
+const REFRESH_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Data of last check, published by daemon for http server.
+pub(crate) struct State {
+    pub metrics: Metrics,
+    pub records: Vec<DomainRecord>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+impl State {
+    pub(crate) fn new() -> Self {
+        return State {
+            metrics: Metrics::new(),
+            records: Vec::new(),
+            updated: None,
+        };
+    }
+}
+
+// Request to daemon for check domain without cache.
+// Daemon send true to response after publish new state or false if domain isn't monitored.
+pub(crate) struct RefreshRequest {
+    pub domain: String,
+    pub response: mpsc::Sender<bool>,
+}
+
+struct Filter {
+    expiring_days: Option<i64>,
+    errors: bool,
+    account: Option<String>,
+}
+
+impl Filter {
+    fn from_query(query: &str) -> Self {
+        let mut res = Filter {
+            expiring_days: None,
+            errors: false,
+            account: None,
+        };
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "expiring" => res.expiring_days = value.parse().ok(),
+                "errors" => res.errors = value == "1" || value == "true",
+                "account" if !value.is_empty() => res.account = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        return res;
+    }
+
+    fn is_match(&self, record: &DomainRecord) -> bool {
+        if let Some(days) = self.expiring_days {
+            match record.days_left {
+                Some(days_left) if days_left <= days => {}
+                _ => return false,
+            }
+        }
+        if self.errors && record.error.is_none() {
+            return false;
+        }
+        if let Some(account) = &self.account {
+            if !record
+                .account
+                .to_lowercase()
+                .contains(&account.to_lowercase())
+            {
+                return false;
+            }
+        }
+        return true;
+    }
+}
+
+// Start http server in background thread.
+pub(crate) fn start(
+    log: &slog::Logger,
+    listen: &str,
+    state: Arc<Mutex<State>>,
+    refresh: mpsc::Sender<RefreshRequest>,
+) -> Result<()> {
+    let server = Server::http(listen).map_err(|err| Error::HttpServerError(err.to_string()))?;
+    let log = log.new(o!("http-listen"=>listen.to_string()));
+    info!(log, "Http server started");
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            debug!(log, "Http request"; "method"=>request.method().to_string(), "url"=>request.url());
+            if let Err(err) = handle(&log, request, &state, &refresh) {
+                error!(log, "Can't send http response"; "error"=>err.to_string());
+            }
+        }
+    });
+    return Ok(());
+}
+
+fn handle(
+    log: &slog::Logger,
+    request: Request,
+    state: &Arc<Mutex<State>>,
+    refresh: &mpsc::Sender<RefreshRequest>,
+) -> std::io::Result<()> {
+    let url = request.url().to_string();
+    let (path, query) = match url.find('?') {
+        Some(index) => (&url[..index], &url[index + 1..]),
+        None => (url.as_str(), ""),
+    };
+    let path = percent_decode(path.as_bytes())
+        .decode_utf8_lossy()
+        .to_string();
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (request.method(), parts.as_slice()) {
+        (Method::Get, [""]) => {
+            let html = render_dashboard(&state.lock().unwrap(), &Filter::from_query(query));
+            return request.respond(
+                Response::from_string(html)
+                    .with_header(header("Content-Type", "text/html; charset=utf-8")),
+            );
+        }
+        (Method::Get, ["metrics"]) => {
+            let text = state.lock().unwrap().metrics.render();
+            return request.respond(
+                Response::from_string(text)
+                    .with_header(header("Content-Type", "text/plain; version=0.0.4")),
+            );
+        }
+        (Method::Get, ["api", "customers"]) => {
+            let state = state.lock().unwrap();
+            let filter = Filter::from_query(query);
+            let mut customers = BTreeMap::new();
+            for record in state
+                .records
+                .iter()
+                .filter(|record| filter.is_match(record))
+            {
+                customers
+                    .entry(record.customer.clone())
+                    .or_insert_with(Vec::new)
+                    .push(record);
+            }
+            let items: Vec<json::JsonValue> = customers
+                .into_iter()
+                .map(|(name, mut records)| {
+                    sort_records(&mut records);
+                    let domains: Vec<json::JsonValue> =
+                        records.iter().map(|record| record.to_json()).collect();
+                    json::object! {
+                        "name" => name,
+                        "domains" => domains,
+                    }
+                })
+                .collect();
+            return respond_json(request, 200, json::JsonValue::Array(items));
+        }
+        (Method::Get, ["api", "domains", domain]) => {
+            let records = domain_records(&state.lock().unwrap(), domain);
+            if records.is_empty() {
+                return respond_json(request, 404, not_found_json());
+            }
+            return respond_json(request, 200, json::JsonValue::Array(records));
+        }
+        (Method::Post, ["api", "domains", domain, "refresh"]) => {
+            // Daemon checks domain up to REFRESH_TIMEOUT: wait for it in own thread,
+            // so other requests are served meanwhile.
+            let log = log.clone();
+            let domain = domain.to_string();
+            let state = Arc::clone(state);
+            let refresh = refresh.clone();
+            thread::spawn(move || {
+                if let Err(err) = refresh_domain(request, &state, &refresh, &domain) {
+                    error!(log, "Can't send http response"; "error"=>err.to_string());
+                }
+            });
+            return Ok(());
+        }
+        _ => {
+            return request.respond(Response::from_string("Not found").with_status_code(404));
+        }
+    }
+}
+
+// Check domain by daemon without cache and respond with new records of domain.
+fn refresh_domain(
+    request: Request,
+    state: &Mutex<State>,
+    refresh: &mpsc::Sender<RefreshRequest>,
+    domain: &str,
+) -> std::io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    let refresh_request = RefreshRequest {
+        domain: domain.to_string(),
+        response: sender,
+    };
+    let found = refresh.send(refresh_request).is_ok()
+        && receiver.recv_timeout(REFRESH_TIMEOUT).unwrap_or(false);
+    if !found {
+        return respond_json(request, 404, not_found_json());
+    }
+    let records = domain_records(&state.lock().unwrap(), domain);
+    return respond_json(request, 200, json::JsonValue::Array(records));
+}
+
+fn domain_records(state: &State, domain: &str) -> Vec<json::JsonValue> {
+    return state
+        .records
+        .iter()
+        .filter(|record| ascii_domain(&record.domain) == ascii_domain(domain))
+        .map(|record| record.to_json())
+        .collect();
+}
+
+fn not_found_json() -> json::JsonValue {
+    return json::object! {
+        "error" => "Domain not found",
+    };
+}
+
+fn respond_json(request: Request, status: u16, value: json::JsonValue) -> std::io::Result<()> {
+    return request.respond(
+        Response::from_string(value.pretty(2))
+            .with_status_code(status)
+            .with_header(header("Content-Type", "application/json")),
+    );
+}
+
+fn header(name: &str, value: &str) -> Header {
+    return Header::from_str(&format!("{}: {}", name, value)).unwrap();
+}
+
+// Same order as in email report: errors, then by expire date, disabled at end.
+fn sort_records(records: &mut Vec<&DomainRecord>) {
+    records.sort_by(|r1, r2| {
+        let order = |record: &DomainRecord| {
+            if record.error.is_some() {
+                0
+            } else if record.expire.is_some() {
+                1
+            } else {
+                2
+            }
+        };
+        order(r1)
+            .cmp(&order(r2))
+            .then_with(|| r1.days_left.cmp(&r2.days_left))
+            .then_with(|| r1.domain.cmp(&r2.domain))
+    });
+}
+
+fn render_dashboard(state: &State, filter: &Filter) -> String {
+    let mut customers = BTreeMap::new();
+    for record in state
+        .records
+        .iter()
+        .filter(|record| filter.is_match(record))
+    {
+        customers
+            .entry(record.customer.as_str())
+            .or_insert_with(Vec::new)
+            .push(record);
+    }
+
+    let mut res = String::new();
+    res += "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Whois monitoring</title>\n";
+    res += "<style>table{border-collapse:collapse}td,th{border:1px solid #999;padding:2px 6px}";
+    res +=
+        ".error,.expired{background:#f99}.expiring{background:#fd8}.disabled{color:#999}</style>\n";
+    res += "</head><body>\n<h1>Whois monitoring</h1>\n";
+    res += &format!(
+        "<p>Updated: {}</p>\n",
+        state
+            .updated
+            .map(|updated| updated.to_rfc3339())
+            .unwrap_or_else(|| "never".to_string())
+    );
+    res += &format!(
+        "<form method=\"get\">Expiring within <input name=\"expiring\" size=\"4\" value=\"{}\"> days \
+         <label><input type=\"checkbox\" name=\"errors\" value=\"1\"{}> Errors only</label> \
+         Account <input name=\"account\" value=\"{}\"> <input type=\"submit\" value=\"Filter\"></form>\n",
+        filter
+            .expiring_days
+            .map(|days| days.to_string())
+            .unwrap_or_default(),
+        if filter.errors { " checked" } else { "" },
+        escape_html(filter.account.as_ref().map(String::as_str).unwrap_or(""))
+    );
+
+    for (customer, mut records) in customers {
+        sort_records(&mut records);
+        res += &format!("<h2>{}</h2>\n<table>\n", escape_html(customer));
+        res += "<tr><th>Domain</th><th>Account</th><th>Expired</th><th>Days left</th><th>Autorenew</th><th>Status</th></tr>\n";
+        for record in records {
+            res += &format!(
+                "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                record.status,
+                escape_html(&record.domain),
+                escape_html(&record.account),
+                escape_html(record.expire.as_ref().map(String::as_str).unwrap_or("")),
+                record
+                    .days_left
+                    .map(|days| days.to_string())
+                    .unwrap_or_default(),
+                record.autorenew,
+                escape_html(record.error.as_ref().map(String::as_str).unwrap_or(record.status)),
+            );
+        }
+        res += "</table>\n";
+    }
+    res += "</body></html>\n";
+    return res;
+}
+
+fn escape_html(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
@@ -0,0 +1,361 @@
+use {
+    crate::{
+        config::Config,
+        customers_config::{normalize_domain, unicode_domain, AccountConfig, CustomerConfig},
+        errors::{Error, Result},
+        public_suffix::SuffixList,
+        registrar::{self, Registrars},
+    },
+    slog::{info, warn},
+    std::collections::{BTreeMap, BTreeSet},
+    std::fs,
+};
+
+// Header names of domain column in registrar exports, lowercase, by priority:
+// Namecheap - "Domain", reg.ru and nic.ru - "Домен" or "dname".
+// Other exports need column name from command line.
+const DOMAIN_COLUMNS: &[&str] = &["domain", "domain name", "домен", "доменное имя", "dname"];
+
+// Domains of registrar account compared with customers.
+struct ImportResult {
+    account_domains: usize,
+
+    // Domains of account, which aren't in customers.
+    unmonitored: Vec<String>,
+
+    // Domains of the account in customers, which aren't in account: domain and customers.
+    missing: Vec<(String, Vec<String>)>,
+}
+
+// Most frequent delimiter of first lines: header may be preceded by title lines.
+fn detect_delimiter(text: &str) -> u8 {
+    let lines: Vec<&str> = text.lines().take(10).collect();
+    let mut res = b',';
+    let mut max_count = 0;
+    for delimiter in &[b',', b';', b'\t'] {
+        let count: usize = lines
+            .iter()
+            .map(|line| line.bytes().filter(|c| c == delimiter).count())
+            .sum();
+        if count > max_count {
+            res = *delimiter;
+            max_count = count;
+        }
+    }
+    return res;
+}
+
+// Index of domain column, if record is header: column with given name or known header.
+fn domain_column(record: &csv::StringRecord, column: Option<&str>) -> Option<usize> {
+    let headers: Vec<String> = record
+        .iter()
+        .map(|header| header.trim_start_matches('\u{feff}').trim().to_lowercase())
+        .collect();
+    let names = match column {
+        Some(column) => vec![column.trim().to_lowercase()],
+        None => DOMAIN_COLUMNS.iter().map(|name| name.to_string()).collect(),
+    };
+    return names
+        .iter()
+        .filter_map(|name| headers.iter().position(|header| header == name))
+        .next();
+}
+
+// Ascii names of domains, bad names are skipped with warning.
+fn normalize_domains(log: &slog::Logger, source: &str, names: Vec<String>) -> Vec<String> {
+    let mut res = Vec::new();
+    for name in names {
+        match normalize_domain(&name) {
+            Ok(domain) => res.push(domain),
+            Err(err) => warn!(log, "Skip bad domain"; "source"=>source, "error"=>err),
+        }
+    }
+    return res;
+}
+
+// Ascii names of domains from CSV export of registrar account.
+// Header may be preceded by title lines, domain column is detected by header name.
+fn read_csv(log: &slog::Logger, path: &str, column: Option<&str>) -> Result<Vec<String>> {
+    let bytes = fs::read(path)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(detect_delimiter(&text))
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let mut index = None;
+    let mut names = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let index = match index {
+            Some(index) => index,
+            None => {
+                index = domain_column(&record, column);
+                continue;
+            }
+        };
+        let value = record.get(index).unwrap_or("").trim();
+        if !value.is_empty() {
+            names.push(value.to_string());
+        }
+    }
+    if index.is_none() {
+        let expected = match column {
+            Some(column) => column.to_string(),
+            None => DOMAIN_COLUMNS.join(", "),
+        };
+        return Err(Error::ImportError(format!(
+            "{}: can't find domain column, expected one of headers: {}",
+            path, expected
+        )));
+    }
+    return Ok(normalize_domains(log, path, names));
+}
+
+// Ascii names of domains from registrar API. Credentials of account are taken by
+// registrar/login key or from credentials_ref of domains of the account.
+fn read_api(
+    cfg: &Config,
+    log: &slog::Logger,
+    account: &AccountConfig,
+    customers: &[CustomerConfig],
+) -> Result<Vec<String>> {
+    let registrars = Registrars::from_config(cfg);
+    let registrar_account = registrars
+        .account(account)
+        .or_else(|| {
+            customers
+                .iter()
+                .flat_map(|customer| customer.domains.iter())
+                .filter(|domain| domain.account.key() == account.key())
+                .find_map(|domain| registrars.account(&domain.account))
+        })
+        .ok_or_else(|| {
+            Error::ImportError(format!(
+                "no registrar API credentials of account {}, use export file",
+                account.key()
+            ))
+        })?;
+    let names = registrar::domains(&registrar_account)?;
+    return Ok(normalize_domains(log, "registrar API", names));
+}
+
+fn compare(
+    suffix_list: &SuffixList,
+    account: &AccountConfig,
+    customers: &[CustomerConfig],
+    account_domains: Vec<String>,
+) -> ImportResult {
+    let account_domains: BTreeSet<String> = account_domains.into_iter().collect();
+
+    // Registrable domains of all customers: subdomains are monitored by registration.
+    let mut monitored = BTreeSet::new();
+    let mut missing = BTreeMap::new();
+    for customer in customers {
+        for domain in &customer.domains {
+            let name = suffix_list.registrable_domain(&domain.ascii_name());
+            if domain.account.key() == account.key() && !account_domains.contains(&name) {
+                let customers: &mut Vec<String> = missing.entry(unicode_domain(&name)).or_default();
+                if !customers.contains(&customer.name) {
+                    customers.push(customer.name.clone());
+                }
+            }
+            monitored.insert(name);
+        }
+    }
+    let mut unmonitored: Vec<String> = account_domains
+        .iter()
+        .filter(|domain| !monitored.contains(*domain))
+        .map(|domain| unicode_domain(domain))
+        .collect();
+    unmonitored.sort();
+    return ImportResult {
+        account_domains: account_domains.len(),
+        unmonitored,
+        missing: missing.into_iter().collect(),
+    };
+}
+
+fn format_table(headers: &[&str], table: &[Vec<String>]) -> String {
+    let mut table_config = ascii_table::TableConfig::default();
+    table_config.width = 140;
+    for (index, header) in headers.iter().enumerate() {
+        let mut column = ascii_table::ColumnConfig::default();
+        column.header = header.to_string();
+        table_config.columns.insert(index, column);
+    }
+    return ascii_table::format_table(table, &table_config);
+}
+
+// Compare domains of registrar account from export file or registrar API with customers:
+// domains, which aren't monitored, and domains of the account in customers, which aren't in account.
+pub(crate) fn create_report(
+    cfg: &Config,
+    log: &slog::Logger,
+    customers: &[CustomerConfig],
+    account: &str,
+    file: Option<&str>,
+    column: Option<&str>,
+) -> Result<String> {
+    let account = AccountConfig::from_string(account);
+    let account_domains = match file {
+        Some(path) => read_csv(log, path, column)?,
+        None => read_api(cfg, log, &account, customers)?,
+    };
+    info!(log, "Domains of account read"; "account"=>account.key(), "count"=>account_domains.len());
+    let suffix_list = SuffixList::from_config(log, cfg);
+    let result = compare(&suffix_list, &account, customers, account_domains);
+
+    let mut res = format!("Account: {}\n", account.key());
+    res += &format!(
+        "Domains in account: {}, not monitored: {}, monitored but not in account: {}\n",
+        result.account_domains,
+        result.unmonitored.len(),
+        result.missing.len()
+    );
+    if !result.unmonitored.is_empty() {
+        let table: Vec<_> = result
+            .unmonitored
+            .iter()
+            .map(|domain| vec![domain.clone()])
+            .collect();
+        res += "\nNot monitored domains:\n";
+        res += &format_table(&["Domain"], &table);
+    }
+    if !result.missing.is_empty() {
+        let table: Vec<_> = result
+            .missing
+            .iter()
+            .map(|(domain, customers)| vec![domain.clone(), customers.join(", ")])
+            .collect();
+        res += "\nMonitored domains, which aren't in account:\n";
+        res += &format_table(&["Domain", "Customers"], &table);
+    }
+    return Ok(res);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Export file in temporary directory, removed by test.
+    fn write_export(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}-{}.csv", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        return path.to_string_lossy().to_string();
+    }
+
+    fn read_export(name: &str, text: &str, column: Option<&str>) -> Result<Vec<String>> {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let path = write_export(name, text);
+        let res = read_csv(&log, &path, column);
+        fs::remove_file(&path).unwrap();
+        return res;
+    }
+
+    #[test]
+    fn delimiter_of_first_lines() {
+        assert_eq!(
+            detect_delimiter("Domain,Expires\nexample.com,2027-01-01\n"),
+            b','
+        );
+        assert_eq!(
+            detect_delimiter(
+                "Список услуг\nДомен;Дата окончания;Цена, руб.\nexample.ru;01.01.2027;199\n"
+            ),
+            b';'
+        );
+        assert_eq!(
+            detect_delimiter("dname\tstate\nexample.ru\tREGISTERED\n"),
+            b'\t'
+        );
+        assert_eq!(detect_delimiter("example.com\n"), b',');
+    }
+
+    #[test]
+    fn namecheap_export() {
+        let text = "Domain Name,Expiration Date,Status,Auto-Renew\n\
+                    Example.com,08/09/2027,Active,Yes\n\
+                    sub-example.net,01/01/2030,Active,No\n";
+        assert_eq!(
+            read_export("namecheap", text, None).unwrap(),
+            vec!["example.com", "sub-example.net"]
+        );
+    }
+
+    #[test]
+    fn regru_export() {
+        let text = "Список услуг аккаунта qweqwe\n\
+                    \n\
+                    Домен;Тип услуги;Дата окончания;Стоимость продления, руб.\n\
+                    example.ru;Регистрация домена;01.01.2027;199\n\
+                    пример.рф;Регистрация домена;02.02.2027;199\n\
+                    ;;;\n";
+        assert_eq!(
+            read_export("regru", text, None).unwrap(),
+            vec!["example.ru", "xn--e1afmkfd.xn--p1ai"]
+        );
+    }
+
+    #[test]
+    fn nicru_export() {
+        let text = "\u{feff}dname\tstate\texpire\n\
+                    EXAMPLE.SU\tREGISTERED\t2027-03-03\n\
+                    bad_domain\tREGISTERED\t2027-03-03\n";
+        assert_eq!(
+            read_export("nicru", text, None).unwrap(),
+            vec!["example.su"]
+        );
+    }
+
+    #[test]
+    fn export_column_from_command_line() {
+        let text = "Name,Hosting,Expires\nhost1,example.com,2027-01-01\n";
+        assert_eq!(
+            read_export("column", text, Some("Hosting")).unwrap(),
+            vec!["example.com"]
+        );
+        assert!(read_export("column", text, None)
+            .unwrap_err()
+            .to_string()
+            .contains("can't find domain column"));
+    }
+
+    #[test]
+    fn compare_with_customers() {
+        let customers: Vec<CustomerConfig> = serde_yaml::from_str(
+            "
+- name: c1
+  emails: []
+  domains:
+    - domain: www.example.com
+      account: reg.ru/Login
+    - domain: missing.com
+      account: reg.ru/login
+- name: c2
+  emails: []
+  domains:
+    - domain: missing.com
+      account: REG.RU/LOGIN
+    - domain: other.com
+      account: reg.ru/other
+",
+        )
+        .unwrap();
+        let result = compare(
+            &SuffixList::empty(),
+            &AccountConfig::from_string("reg.ru/login"),
+            &customers,
+            vec!["www.example.com".to_string(), "new.com".to_string()],
+        );
+        assert_eq!(result.account_domains, 2);
+        assert_eq!(result.unmonitored, vec!["new.com"]);
+        assert_eq!(
+            result.missing,
+            vec![(
+                "missing.com".to_string(),
+                vec!["c1".to_string(), "c2".to_string()]
+            )]
+        );
+    }
+}
@@ -1,26 +1,42 @@
 mod account_checker;
+mod archive;
 mod cache;
+mod calendar;
+mod check_plugin;
 mod config;
 mod customers_config;
+mod daemon;
 mod errors;
+mod export;
 mod flags;
+mod forecast;
+mod http_server;
+mod import;
+mod metrics;
+mod net;
+mod public_suffix;
+mod registrar;
+mod storage;
+mod validation;
+mod whois;
 
 use {
     crate::{
         account_checker::{AccountChecker, CheckAccountResult},
         config::Config,
         customers_config::CustomerConfig,
-        errors::Result,
+        errors::{Error, Result},
     },
     chrono::{DateTime, Datelike, Utc},
+    chrono_tz::Tz,
     lettre::Transport,
     rand,
     rand::prelude::*,
     slog::{debug, error, info, o, Drain, Level},
     slog_unwraps::ResultExt,
-    std::collections::HashMap,
     std::fs,
     std::io,
+    std::process,
 };
 
 const CUSTOMERS_EXAMPLE_YAML: &str = include_str!("../customers-example.yaml");
@@ -28,10 +44,8 @@ const CUSTOMERS_EXAMPLE_YAML: &str = include_str!("../customers-example.yaml");
 fn get_config(fname: &str) -> Result<Config> {
     return Config::from_file(fname);
 }
-fn get_customers(fname: &str) -> Result<Vec<CustomerConfig>> {
-    let f = fs::File::open(fname)?;
-    let config = serde_yaml::from_reader(f)?;
-    return Ok(config);
+fn get_customers(paths: &[String]) -> Result<Vec<CustomerConfig>> {
+    return customers_config::load_customers(paths);
 }
 
 fn is_need_send(
@@ -52,7 +66,8 @@ fn is_need_send(
         return true;
     }
 
-    return cfg.ok_report_day - 1 == now.weekday() as u8;
+    // Report day is in config timezone, like daemon report time.
+    return cfg.ok_report_day - 1 == now.with_timezone(&cfg.timezone).weekday() as u8;
 }
 
 enum CreateEmailParams {
@@ -60,23 +75,120 @@ enum CreateEmailParams {
     ToAdmin,
 }
 
+fn customer_timezone(cfg: &Config, customer: &CustomerConfig) -> Tz {
+    return customer.timezone.unwrap_or(cfg.timezone);
+}
+
 fn create_email(
     params: CreateEmailParams,
-    from: &str,
+    cfg: &Config,
     customer: &CustomerConfig,
     check_result: &CheckAccountResult,
-) -> lettre_email::EmailBuilder {
+    now: &DateTime<Utc>,
+) -> Result<lettre_email::EmailBuilder> {
     let subject = match params {
         CreateEmailParams::ToAdmin => format!("Отчет по доменам - {}", customer.name),
         CreateEmailParams::ToCustomer => "Отчет по доменам".to_string(),
     };
-    let report_text = account_checker::create_account_report(check_result);
+    let report_text =
+        account_checker::create_account_report(check_result, now, customer_timezone(cfg, customer));
     let res = lettre_email::Email::builder()
         .subject(subject)
-        .from(from)
+        .from(cfg.smtp_from.as_str())
         .alternative(format!("<pre>\n{}\n</pre>", report_text), &report_text);
 
-    return res;
+    return attach_calendar(cfg, res, &[(customer, check_result)], now);
+}
+
+fn attach_calendar(
+    cfg: &Config,
+    email: lettre_email::EmailBuilder,
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+    now: &DateTime<Utc>,
+) -> Result<lettre_email::EmailBuilder> {
+    if !cfg.ics_attachment {
+        return Ok(email);
+    }
+    let calendar = calendar::create_calendar(results, cfg.expire_soon_days, now);
+    let content_type: lettre_email::mime::Mime = "text/calendar; charset=utf-8".parse().unwrap();
+    return Ok(email.attachment(
+        calendar.as_bytes(),
+        calendar::CALENDAR_FILE_NAME,
+        &content_type,
+    )?);
+}
+
+fn create_admin_digest(
+    cfg: &Config,
+    log: &slog::Logger,
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+    now: &DateTime<Utc>,
+) -> Result<lettre_email::EmailBuilder> {
+    let mut customers: Vec<_> = results
+        .iter()
+        .map(|(customer, check_result)| {
+            let summary = account_checker::account_summary(cfg, check_result, now);
+            (*customer, *check_result, summary)
+        })
+        .collect();
+    customers.sort_by(|(c1, _, s1), (c2, _, s2)| {
+        s2.need_attention()
+            .cmp(&s1.need_attention())
+            .then_with(|| c1.name.cmp(&c2.name))
+    });
+
+    let mut report_text = String::new();
+
+    let attention: Vec<&str> = customers
+        .iter()
+        .filter(|(_, _, summary)| summary.need_attention())
+        .map(|(customer, _, _)| customer.name.as_str())
+        .collect();
+    if !attention.is_empty() {
+        report_text += &format!("Need attention: {}\n\n", attention.join(", "));
+    }
+
+    let mut table = vec![];
+    for (customer, _, summary) in &customers {
+        table.push([
+            customer.name.clone(),
+            summary.expiring.to_string(),
+            summary.expired.to_string(),
+            summary.errors.to_string(),
+        ]);
+    }
+    let mut table_config = ascii_table::TableConfig::default();
+    table_config.width = 140;
+    for (index, header) in ["Customer", "Expiring", "Expired", "Errors"]
+        .iter()
+        .enumerate()
+    {
+        let mut column = ascii_table::ColumnConfig::default();
+        column.header = header.to_string();
+        table_config.columns.insert(index, column);
+    }
+    report_text += &ascii_table::format_table(&table, &table_config);
+
+    for (customer, check_result, _) in &customers {
+        report_text += &format!("\n\n{}\n", customer.name);
+        report_text += &account_checker::create_account_report(
+            check_result,
+            now,
+            customer_timezone(cfg, customer),
+        );
+    }
+
+    if cfg.admin_digest_forecast {
+        report_text += "\n\nRenewal forecast\n";
+        report_text += &forecast::create_report(cfg, log, results, now);
+    }
+
+    let res = lettre_email::Email::builder()
+        .subject("Сводный отчет по доменам")
+        .from(cfg.smtp_from.as_str())
+        .alternative(format!("<pre>\n{}\n</pre>", report_text), &report_text);
+
+    return attach_calendar(cfg, res, results, now);
 }
 
 fn create_logger(cfg: &Config) -> slog::Logger {
@@ -123,6 +235,19 @@ fn send_email(log: &slog::Logger, cfg: &Config, email: lettre_email::EmailBuilde
     return Ok(());
 }
 
+// Lock state for the run and load cache of checker.
+fn open_storage(
+    log: &slog::Logger,
+    cfg: &Config,
+    checker: &mut AccountChecker,
+    now: DateTime<Utc>,
+) -> Result<(Option<storage::StateLock>, storage::Storage)> {
+    let lock = storage::lock_state(log, cfg)?;
+    let storage = storage::Storage::open(log, cfg)?;
+    storage.load(log, checker, now, &cache::CachePolicy::from_config(cfg))?;
+    return Ok((lock, storage));
+}
+
 fn main() -> Result<()> {
     let now = chrono::Utc::now();
 
@@ -138,40 +263,109 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let cfg = get_config(&opt.config)?;
+    let is_check_plugin = match &opt.command {
+        Some(flags::Command::CheckPlugin { .. }) => true,
+        _ => false,
+    };
+
+    let cfg = match get_config(&opt.config) {
+        Ok(cfg) => cfg,
+        Err(err) if is_check_plugin => {
+            println!("WHOIS UNKNOWN - {}", err);
+            process::exit(check_plugin::PluginState::Unknown as i32);
+        }
+        Err(err) => return Err(err),
+    };
 
     let log = &create_logger(&cfg);
 
-    let mut checker = AccountChecker::new();
+    let command = opt.command.unwrap_or(flags::Command::Run);
+    match &command {
+        flags::Command::Check { domains } => return check_domains(&now, &cfg, log, domains),
+        flags::Command::Config {
+            cmd: flags::ConfigCommand::Validate,
+        } => return validate_config(log, &cfg, &opt.config),
+        flags::Command::Balance => {
+            let registrars = registrar::Registrars::from_config(&cfg);
+            println!("{}", registrar::create_balance_report(log, &registrars));
+            return Ok(());
+        }
+        flags::Command::Import {
+            account,
+            file,
+            column,
+        } => {
+            let customers = get_customers(&cfg.customers_file)?;
+            let report = import::create_report(
+                &cfg,
+                log,
+                &customers,
+                account,
+                file.as_ref().map(String::as_str),
+                column.as_ref().map(String::as_str),
+            )?;
+            print!("{}", report);
+            return Ok(());
+        }
+        _ => {}
+    }
 
-    if cfg.state_file.is_empty() {
-        debug!(log, "State file path is empty. Doesn't load state.")
-    } else {
-        info!(log, "Load state"; "file"=>&cfg.state_file);
-        match fs::File::open(&cfg.state_file) {
-            Err(err) => {
-                if err.kind() == io::ErrorKind::NotFound {
-                    info!(log, "State file not found.")
-                } else {
-                    error!(log, "State file error. You can remove it for reset cache.");
-                    return Err(errors::Error::from(err));
-                }
-            }
-            Ok(reader) => {
-                // Ignore cache error
-                let _ = checker
-                    .load_state(log, reader, now, cfg.no_cache_days_before_expire)
-                    .log(log, Level::Error);
-            }
+    let mut checker = AccountChecker::new(
+        public_suffix::SuffixList::from_config(log, &cfg),
+        whois::WhoisClient::from_config(&cfg),
+        registrar::Registrars::from_config(&cfg),
+    );
+
+    let (_lock, mut storage) = match open_storage(log, &cfg, &mut checker, now) {
+        Ok(res) => res,
+        Err(err) if is_check_plugin => {
+            // State is locked by daemon or other run: check result is unknown, not warning.
+            println!("WHOIS UNKNOWN - {}", err);
+            process::exit(check_plugin::PluginState::Unknown as i32);
         }
+        Err(err) => return Err(err),
     };
 
+    if let flags::Command::Cache { cmd } = &command {
+        cache_command(log, &mut checker, cmd);
+        return storage.save(&mut checker);
+    }
+
+    if let flags::Command::Archive {
+        domain,
+        count,
+        diff,
+    } = &command
+    {
+        let entries = storage.whois_archive(&checker.lookup_name(domain), *count)?;
+        if entries.is_empty() {
+            println!("No archived responses of {}", domain);
+        } else {
+            print!("{}", archive::create_report(&entries, *diff));
+        }
+        return Ok(());
+    }
+
+    if let flags::Command::History { domain } = &command {
+        let history = storage.domain_history(&checker.lookup_name(domain))?;
+        if history.is_empty() {
+            println!("No lookups of {}", domain);
+        } else {
+            println!("{}", storage::create_history_report(&history));
+        }
+        return Ok(());
+    }
+
     let customers = match get_customers(&cfg.customers_file) {
         Err(err) => {
             error!(log,
                 "Error while load costomers config";
-                "file"=>&cfg.customers_file, "error"=>err.to_string()
+                "file"=>cfg.customers_file.join(", "), "error"=>err.to_string()
             );
+            if is_check_plugin {
+                println!("WHOIS UNKNOWN - {}", err);
+                process::exit(check_plugin::PluginState::Unknown as i32);
+            }
             return Err(err);
         }
         Ok(customers) => {
@@ -180,14 +374,92 @@ fn main() -> Result<()> {
         }
     };
 
-    run(&now, &cfg, &log, &mut checker, &customers);
-
-    if !cfg.state_file.is_empty() {
-        let writer = fs::File::create(&cfg.state_file)?;
-        checker.save_state(writer)?
+    match &command {
+        flags::Command::Run => {
+            let mut metrics = metrics::Metrics::new();
+            let notifications = run(&now, &cfg, &log, &mut checker, &customers, &mut metrics);
+            let _ = storage
+                .save_notifications(&notifications)
+                .log(&log, Level::Error);
+            if !cfg.metrics_textfile.is_empty() {
+                metrics::write_textfile(&cfg.metrics_textfile, &metrics)?;
+            }
+        }
+        flags::Command::Daemon => {
+            daemon::run_daemon(&cfg, &log, &mut checker, &mut storage, &customers)?
+        }
+        flags::Command::CheckPlugin {
+            customers: customer_names,
+            domains,
+            warning,
+            critical,
+        } => {
+            let state = run_check_plugin(
+                &now,
+                &log,
+                &mut checker,
+                &customers,
+                customer_names,
+                domains,
+                *warning,
+                *critical,
+            );
+            // Status is printed already, error of save doesn't change it.
+            let _ = storage.save(&mut checker).log(&log, Level::Error);
+            process::exit(state as i32);
+        }
+        flags::Command::Report { customer } => {
+            print_report(&now, &cfg, &log, &mut checker, &customers, customer)?
+        }
+        flags::Command::Calendar { output } => {
+            write_calendar(&now, &cfg, &log, &mut checker, &customers, output)?
+        }
+        flags::Command::Accounts { accounts, expiring } => {
+            let results = check_customers(&log, &mut checker, &customers);
+            let results: Vec<_> = results
+                .iter()
+                .map(|(customer, res)| (*customer, res))
+                .collect();
+            println!(
+                "{}",
+                account_checker::create_accounts_report(
+                    &results,
+                    accounts,
+                    *expiring,
+                    &now,
+                    cfg.timezone
+                )
+            );
+        }
+        flags::Command::Forecast => {
+            let results = check_customers(&log, &mut checker, &customers);
+            let results: Vec<_> = results
+                .iter()
+                .map(|(customer, res)| (*customer, res))
+                .collect();
+            println!("{}", forecast::create_report(&cfg, &log, &results, &now));
+        }
+        flags::Command::Export { format, output } => export(
+            &now,
+            &cfg,
+            &log,
+            &mut checker,
+            &customers,
+            format,
+            output.as_ref().map(String::as_str),
+        )?,
+        flags::Command::Check { .. }
+        | flags::Command::Cache { .. }
+        | flags::Command::Config { .. }
+        | flags::Command::History { .. }
+        | flags::Command::Archive { .. }
+        | flags::Command::Import { .. }
+        | flags::Command::Balance => {
+            unreachable!()
+        }
     }
 
-    return Ok(());
+    return storage.save(&mut checker);
 }
 
 fn run(
@@ -196,44 +468,327 @@ fn run(
     log: &slog::Logger,
     checker: &mut AccountChecker,
     customers: &Vec<CustomerConfig>,
-) {
-    let mut h = HashMap::new();
-    for account in customers {
-        if account.disabled {
-            continue;
-        }
-        h.insert(account, checker.check_account(log, &account));
-    }
-    for (customer, check_result) in h.iter() {
+    metrics: &mut metrics::Metrics,
+) -> Vec<storage::Notification> {
+    let results = check_customers(log, checker, customers);
+    let results: Vec<_> = results
+        .iter()
+        .map(|(customer, res)| (*customer, res))
+        .collect();
+    metrics.update_domains(&results, &checker.stats);
+    let notifications = send_reports(now, cfg, log, &results);
+    metrics.emails_sent(&notifications);
+    return notifications;
+}
+
+fn notification(
+    now: &DateTime<Utc>,
+    customer: &str,
+    dest: &'static str,
+    email: &str,
+    res: &Result<()>,
+) -> storage::Notification {
+    return storage::Notification {
+        time: *now,
+        customer: customer.to_string(),
+        dest,
+        email: email.to_string(),
+        error: res.as_ref().err().map(|err| err.to_string()),
+    };
+}
+
+// Email with OFF: prefix is disabled, reports aren't sent to it.
+fn is_email_off(email: &str) -> bool {
+    return email.to_lowercase().starts_with("off:");
+}
+
+fn send_reports(
+    now: &DateTime<Utc>,
+    cfg: &Config,
+    log: &slog::Logger,
+    results: &[(&CustomerConfig, &CheckAccountResult)],
+) -> Vec<storage::Notification> {
+    let mut notifications = Vec::new();
+    let mut need_send_digest = false;
+    for (customer, check_result) in results {
         let log = &log.new(o!("customer"=>customer.name.clone()));
 
         if is_need_send(&cfg, *customer, check_result, &now) {
             debug!(log, "Need send report");
-            let admin_email = create_email(
-                CreateEmailParams::ToAdmin,
-                cfg.smtp_from.as_str(),
-                *customer,
-                check_result,
-            );
-            for to in &cfg.admin_emails {
-                let log = &log.new(o!("dest"=>"admin", "email"=>to.clone()));
-                let _ = send_email(log, &cfg, admin_email.clone().to(to.as_str()));
+            if cfg.admin_digest {
+                need_send_digest = true;
+            } else {
+                if let Ok(admin_email) = create_email(
+                    CreateEmailParams::ToAdmin,
+                    &cfg,
+                    *customer,
+                    check_result,
+                    &now,
+                )
+                .log(log, Level::Error)
+                {
+                    for to in cfg.admin_emails.iter().filter(|to| !is_email_off(to)) {
+                        let log = &log.new(o!("dest"=>"admin", "email"=>to.clone()));
+                        let res = send_email(log, &cfg, admin_email.clone().to(to.as_str()));
+                        notifications.push(notification(now, &customer.name, "admin", to, &res));
+                    }
+                }
             }
-            let customer_email = create_email(
+            let customer_email = match create_email(
                 CreateEmailParams::ToCustomer,
-                cfg.smtp_from.as_str(),
+                &cfg,
                 *customer,
                 check_result,
-            );
+                &now,
+            )
+            .log(log, Level::Error)
+            {
+                Ok(email) => email,
+                Err(_) => continue,
+            };
             for to in &customer.emails {
-                if to.clone().to_lowercase().starts_with("off:") {
+                if is_email_off(to) {
                     continue;
                 }
                 let log = &log.new(o!("dest"=>"customer", "email"=>to.clone()));
-                let _ = send_email(log, &cfg, customer_email.clone().to(to.as_str()));
+                let res = send_email(log, &cfg, customer_email.clone().to(to.as_str()));
+                notifications.push(notification(now, &customer.name, "customer", to, &res));
             }
         } else {
             debug!(log, "No need send record");
         }
     }
+
+    if need_send_digest {
+        debug!(log, "Need send admin digest");
+        if let Ok(digest_email) =
+            create_admin_digest(&cfg, log, results, &now).log(log, Level::Error)
+        {
+            for to in cfg.admin_emails.iter().filter(|to| !is_email_off(to)) {
+                let log = &log.new(o!("dest"=>"admin-digest", "email"=>to.clone()));
+                let res = send_email(log, &cfg, digest_email.clone().to(to.as_str()));
+                notifications.push(notification(now, "", "admin-digest", to, &res));
+            }
+        }
+    }
+    return notifications;
+}
+
+fn write_calendar(
+    now: &DateTime<Utc>,
+    cfg: &Config,
+    log: &slog::Logger,
+    checker: &mut AccountChecker,
+    customers: &Vec<CustomerConfig>,
+    output: &str,
+) -> Result<()> {
+    let mut results = Vec::new();
+    for customer in customers {
+        if customer.disabled {
+            continue;
+        }
+        results.push((customer, checker.check_account(log, &customer)));
+    }
+    let results: Vec<_> = results
+        .iter()
+        .map(|(customer, res)| (*customer, res))
+        .collect();
+    let calendar = calendar::create_calendar(&results, cfg.expire_soon_days, now);
+    fs::write(output, calendar)?;
+    info!(log, "Calendar saved"; "file"=>output);
+    return Ok(());
+}
+
+fn export(
+    now: &DateTime<Utc>,
+    cfg: &Config,
+    log: &slog::Logger,
+    checker: &mut AccountChecker,
+    customers: &Vec<CustomerConfig>,
+    format: &export::Format,
+    output: Option<&str>,
+) -> Result<()> {
+    let results = check_customers(log, checker, customers);
+    let results: Vec<_> = results
+        .iter()
+        .map(|(customer, res)| (*customer, res))
+        .collect();
+    let records = export::create_records(cfg, &results, now);
+    match output {
+        None => export::write_records(io::stdout(), format, &records)?,
+        Some(fname) => {
+            export::write_records(fs::File::create(fname)?, format, &records)?;
+            info!(log, "Export saved"; "file"=>fname);
+        }
+    }
+    return Ok(());
+}
+
+fn check_customers<'a>(
+    log: &slog::Logger,
+    checker: &mut AccountChecker,
+    customers: &'a Vec<CustomerConfig>,
+) -> Vec<(&'a CustomerConfig, CheckAccountResult<'a>)> {
+    let mut res = Vec::new();
+    for customer in customers {
+        if customer.disabled {
+            continue;
+        }
+        res.push((customer, checker.check_account(log, &customer)));
+    }
+    return res;
+}
+
+fn check_domains(
+    now: &DateTime<Utc>,
+    cfg: &Config,
+    log: &slog::Logger,
+    domains: &[String],
+) -> Result<()> {
+    let mut checker = AccountChecker::new(
+        public_suffix::SuffixList::from_config(log, cfg),
+        whois::WhoisClient::from_config(cfg),
+        registrar::Registrars::from_config(cfg),
+    );
+    for domain in domains {
+        let log = &log.new(o!("domain"=>domain.clone()));
+        println!("Domain: {}", domain);
+        let name = checker.lookup_name(domain);
+        if name != customers_config::ascii_domain(domain) {
+            println!("Registrable domain: {}", name);
+        }
+        let whois = match checker.lookup(log, &name, None) {
+            Ok(whois) => whois,
+            Err(err) => {
+                println!("Error: {}\n", err);
+                continue;
+            }
+        };
+        println!("Server: {}", whois.server);
+        match account_checker::parse_expire_date(&whois.text) {
+            Ok(expire) => println!(
+                "Expire: {} (days left: {})",
+                expire.to_rfc3339(),
+                account_checker::days_left(&expire, now)
+            ),
+            Err(err) => println!("Expire: {}", err),
+        }
+        println!("Raw whois:\n{}\n", whois.text);
+    }
+    return Ok(());
+}
+
+fn print_report(
+    now: &DateTime<Utc>,
+    cfg: &Config,
+    log: &slog::Logger,
+    checker: &mut AccountChecker,
+    customers: &Vec<CustomerConfig>,
+    name: &str,
+) -> Result<()> {
+    let customer = match customers.iter().find(|customer| customer.name == name) {
+        Some(customer) => customer,
+        None => return Err(errors::Error::CustomerNotFound(name.to_string())),
+    };
+    let check_result = checker.check_account(log, customer);
+    println!(
+        "{}",
+        account_checker::create_account_report(
+            &check_result,
+            now,
+            customer_timezone(cfg, customer)
+        )
+    );
+    return Ok(());
+}
+
+fn cache_command(log: &slog::Logger, checker: &mut AccountChecker, cmd: &flags::CacheCommand) {
+    match cmd {
+        flags::CacheCommand::Show => println!("{}", checker.cache_mut().create_report()),
+        flags::CacheCommand::Clear => {
+            checker.cache_mut().clear();
+            info!(log, "Cache cleared");
+        }
+        flags::CacheCommand::Invalidate { domains } => {
+            for domain in domains {
+                if checker.invalidate(domain) {
+                    info!(log, "Domain removed from cache"; "domain"=>domain);
+                } else {
+                    info!(log, "Domain not found in cache"; "domain"=>domain);
+                }
+            }
+        }
+    }
+}
+
+fn validate_config(log: &slog::Logger, cfg: &Config, config_file: &str) -> Result<()> {
+    let problems = validation::validate(cfg, config_file);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    let errors_count = problems.iter().filter(|problem| !problem.warning).count();
+    if errors_count > 0 {
+        return Err(Error::ConfigInvalid(errors_count));
+    }
+    info!(log, "Config is valid");
+    println!("Config is valid");
+    return Ok(());
+}
+
+fn run_check_plugin(
+    now: &DateTime<Utc>,
+    log: &slog::Logger,
+    checker: &mut AccountChecker,
+    customers: &Vec<CustomerConfig>,
+    customer_names: &[String],
+    domains: &[String],
+    warning: i64,
+    critical: i64,
+) -> check_plugin::PluginState {
+    for name in customer_names {
+        if !customers.iter().any(|customer| &customer.name == name) {
+            println!(
+                "WHOIS UNKNOWN - {}",
+                errors::Error::CustomerNotFound(name.clone())
+            );
+            return check_plugin::PluginState::Unknown;
+        }
+    }
+
+    // Domains from command line, checked as domains of temporary customer.
+    let adhoc_customer = CustomerConfig {
+        name: "check-plugin".to_string(),
+        disabled: false,
+        timezone: None,
+        emails: Vec::new(),
+        domains: domains
+            .iter()
+            .map(|domain| customers_config::DomainConfig {
+                domain: domain.clone(),
+                account: customers_config::AccountConfig::default(),
+                autorenew: false,
+                disabled: false,
+                whois_server: None,
+            })
+            .collect(),
+    };
+
+    let mut results = Vec::new();
+    let check_all = customer_names.is_empty() && domains.is_empty();
+    for customer in customers {
+        if customer.disabled {
+            continue;
+        }
+        if check_all || customer_names.contains(&customer.name) {
+            results.push(checker.check_account(log, customer));
+        }
+    }
+    if !domains.is_empty() {
+        results.push(checker.check_account(log, &adhoc_customer));
+    }
+
+    let results: Vec<_> = results.iter().collect();
+    let (state, status) = check_plugin::create_status(&results, now, warning, critical);
+    println!("{}", status);
+    return state;
 }
@@ -0,0 +1,200 @@
+use {
+    crate::{
+        account_checker::{CheckAccountResult, CheckDomainResult, LookupStats},
+        customers_config::CustomerConfig,
+        errors::Result,
+        storage::Notification,
+    },
+    std::{fmt::Write, fs, path::Path},
+};
+
+#[derive(Debug, Clone)]
+struct DomainMetric {
+    customer: String,
+    domain: String,
+    account: String,
+    expire: Option<i64>,
+    success: bool,
+}
+
+// Prometheus metrics, rendered in text exposition format.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Metrics {
+    domains: Vec<DomainMetric>,
+    lookup_durations: Vec<(String, f64)>,
+    cache_hits: u64,
+    cache_misses: u64,
+    emails_sent: u64,
+    emails_failed: u64,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        return Metrics::default();
+    }
+
+    // Replace domain gauges by results of last check.
+    pub(crate) fn update_domains(
+        &mut self,
+        results: &[(&CustomerConfig, &CheckAccountResult)],
+        stats: &LookupStats,
+    ) {
+        self.domains.clear();
+        for (customer, check_result) in results {
+            for (domain, res) in check_result.domain_results() {
+                let (expire, success) = match res {
+                    Ok(CheckDomainResult::ExpireDate(expire)) => (Some(expire.timestamp()), true),
+                    Ok(CheckDomainResult::Disabled) => continue,
+                    Err(_) => (None, false),
+                };
+                self.domains.push(DomainMetric {
+                    customer: customer.name.clone(),
+                    domain: domain.ascii_name(),
+                    account: domain.account.key(),
+                    expire,
+                    success,
+                });
+            }
+        }
+        self.domains.sort_by(|d1, d2| {
+            d1.customer
+                .cmp(&d2.customer)
+                .then_with(|| d1.domain.cmp(&d2.domain))
+        });
+
+        self.lookup_durations = stats
+            .lookup_durations
+            .iter()
+            .map(|(domain, duration)| (domain.clone(), *duration))
+            .collect();
+        self.lookup_durations.sort_by(|(d1, _), (d2, _)| d1.cmp(d2));
+        self.cache_hits = stats.cache_hits;
+        self.cache_misses = stats.cache_misses;
+    }
+
+    pub(crate) fn emails_sent(&mut self, notifications: &[Notification]) {
+        for item in notifications {
+            if item.error.is_none() {
+                self.emails_sent += 1;
+            } else {
+                self.emails_failed += 1;
+            }
+        }
+    }
+
+    pub(crate) fn render(&self) -> String {
+        let mut res = String::new();
+
+        write_header(
+            &mut res,
+            "domain_expiry_timestamp_seconds",
+            "gauge",
+            "Domain expire time, unix timestamp.",
+        );
+        for item in &self.domains {
+            if let Some(expire) = item.expire {
+                let _ = writeln!(
+                    res,
+                    "domain_expiry_timestamp_seconds{} {}",
+                    domain_labels(item),
+                    expire
+                );
+            }
+        }
+
+        write_header(
+            &mut res,
+            "domain_lookup_success",
+            "gauge",
+            "1 if expire date of domain was received on last check.",
+        );
+        for item in &self.domains {
+            let _ = writeln!(
+                res,
+                "domain_lookup_success{} {}",
+                domain_labels(item),
+                item.success as u8
+            );
+        }
+
+        write_header(
+            &mut res,
+            "domain_lookup_duration_seconds",
+            "gauge",
+            "Duration of last whois lookup of domain.",
+        );
+        for (domain, duration) in &self.lookup_durations {
+            let _ = writeln!(
+                res,
+                "domain_lookup_duration_seconds{{domain=\"{}\"}} {}",
+                escape_label(domain),
+                duration
+            );
+        }
+
+        write_header(
+            &mut res,
+            "domain_cache_hits_total",
+            "counter",
+            "Domain checks, answered from cache.",
+        );
+        let _ = writeln!(res, "domain_cache_hits_total {}", self.cache_hits);
+
+        write_header(
+            &mut res,
+            "domain_cache_misses_total",
+            "counter",
+            "Domain checks, which required whois lookup.",
+        );
+        let _ = writeln!(res, "domain_cache_misses_total {}", self.cache_misses);
+
+        write_header(
+            &mut res,
+            "emails_sent_total",
+            "counter",
+            "Sent report emails.",
+        );
+        let _ = writeln!(
+            res,
+            "emails_sent_total{{result=\"ok\"}} {}",
+            self.emails_sent
+        );
+        let _ = writeln!(
+            res,
+            "emails_sent_total{{result=\"error\"}} {}",
+            self.emails_failed
+        );
+
+        return res;
+    }
+}
+
+fn write_header(res: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(res, "# HELP {} {}", name, help);
+    let _ = writeln!(res, "# TYPE {} {}", name, metric_type);
+}
+
+fn domain_labels(item: &DomainMetric) -> String {
+    return format!(
+        "{{customer=\"{}\",domain=\"{}\",account=\"{}\"}}",
+        escape_label(&item.customer),
+        escape_label(&item.domain),
+        escape_label(&item.account)
+    );
+}
+
+fn escape_label(value: &str) -> String {
+    return value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+}
+
+// Write file for node_exporter textfile collector.
+// Write to temporary file and rename it, so collector never read half-written file.
+pub(crate) fn write_textfile(fname: &str, metrics: &Metrics) -> Result<()> {
+    let tmp_name = format!("{}.tmp", fname);
+    fs::write(&tmp_name, metrics.render())?;
+    fs::rename(&tmp_name, Path::new(fname))?;
+    return Ok(());
+}
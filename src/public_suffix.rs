@@ -0,0 +1,65 @@
+use {
+    crate::config::Config,
+    slog::{info, warn},
+    std::fs,
+};
+
+// Private domains section (blogspot.com, github.io, ...) is skipped:
+// whois knows registrations of ICANN suffixes only.
+const PRIVATE_DOMAINS_MARKER: &str = "// ===BEGIN PRIVATE DOMAINS===";
+
+// Public Suffix List for derive registrable domain (eTLD+1) from host name.
+pub(crate) struct SuffixList {
+    list: Option<publicsuffix::List>,
+}
+
+impl SuffixList {
+    // List without rules: every domain is registrable.
+    pub(crate) fn empty() -> Self {
+        return SuffixList { list: None };
+    }
+
+    pub(crate) fn from_file(fname: &str) -> std::result::Result<Self, String> {
+        let text = fs::read_to_string(fname).map_err(|err| format!("{}: {}", fname, err))?;
+        let text = match text.find(PRIVATE_DOMAINS_MARKER) {
+            Some(index) => &text[..index],
+            None => text.as_str(),
+        };
+        let list =
+            publicsuffix::List::from_str(text).map_err(|err| format!("{}: {}", fname, err))?;
+        return Ok(SuffixList { list: Some(list) });
+    }
+
+    // Load list from public_suffix_list file. Without list domains are checked as is.
+    pub(crate) fn from_config(log: &slog::Logger, cfg: &Config) -> Self {
+        if cfg.public_suffix_list.is_empty() {
+            return SuffixList::empty();
+        }
+        match SuffixList::from_file(&cfg.public_suffix_list) {
+            Ok(list) => {
+                info!(log, "Public suffix list loaded"; "file"=>&cfg.public_suffix_list);
+                return list;
+            }
+            Err(err) => {
+                warn!(log, "Can't load public suffix list. Domains are checked as is."; "error"=>err);
+                return SuffixList::empty();
+            }
+        }
+    }
+
+    // Registrable domain of ascii domain name: www.example.co.uk -> example.co.uk.
+    // Returns domain as is if it is unknown for list.
+    pub(crate) fn registrable_domain(&self, domain: &str) -> String {
+        let list = match &self.list {
+            Some(list) => list,
+            None => return domain.to_string(),
+        };
+        return match list.parse_domain(domain) {
+            Ok(parsed) if parsed.has_known_suffix() => match parsed.root() {
+                Some(root) => root.to_string(),
+                None => domain.to_string(),
+            },
+            _ => domain.to_string(),
+        };
+    }
+}
@@ -0,0 +1,1336 @@
+use {
+    crate::{
+        config::Config,
+        customers_config::AccountConfig,
+        errors::{Error, Result},
+        net::{self, HttpResponse, Route},
+    },
+    chrono::{DateTime, NaiveDate, TimeZone, Utc},
+    serde::Deserialize,
+    slog::Level,
+    slog_unwraps::ResultExt,
+    std::{collections::HashMap, fmt},
+};
+
+const REGRU_API_URL: &str = "https://api.reg.ru/api/regru2";
+const NICRU_API_URL: &str = "https://www.nic.ru/dns/dealer";
+const NAMECHEAP_API_URL: &str = "https://api.namecheap.com/xml.response";
+const GODADDY_API_URL: &str = "https://api.godaddy.com";
+const CLOUDFLARE_API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub(crate) enum Provider {
+    RegRu,
+    NicRu,
+    Namecheap,
+    GoDaddy,
+    Cloudflare,
+}
+
+impl Provider {
+    pub(crate) fn as_str(&self) -> &'static str {
+        use Provider::*;
+        match self {
+            RegRu => "reg.ru",
+            NicRu => "nic.ru",
+            Namecheap => "namecheap",
+            GoDaddy => "godaddy",
+            Cloudflare => "cloudflare",
+        }
+    }
+
+    // Provider of registrar name from account field: reg.ru, namecheap.com, ...
+    pub(crate) fn from_registrar(registrar: &str) -> Option<Self> {
+        use Provider::*;
+        match registrar.trim().to_lowercase().as_str() {
+            "reg.ru" | "regru" => Some(RegRu),
+            "nic.ru" | "nicru" | "ru-center" => Some(NicRu),
+            "namecheap" | "namecheap.com" => Some(Namecheap),
+            "godaddy" | "godaddy.com" => Some(GoDaddy),
+            "cloudflare" | "cloudflare.com" => Some(Cloudflare),
+            _ => None,
+        }
+    }
+
+    fn default_api_url(&self) -> &'static str {
+        use Provider::*;
+        match self {
+            RegRu => REGRU_API_URL,
+            NicRu => NICRU_API_URL,
+            Namecheap => NAMECHEAP_API_URL,
+            GoDaddy => GODADDY_API_URL,
+            Cloudflare => CLOUDFLARE_API_URL,
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f.write_str(self.as_str());
+    }
+}
+
+// Credentials of registrar account. Used fields depend on provider:
+// RegRu - login, password; NicRu - login (contract), password; Namecheap - login, api_key, client_ip;
+// GoDaddy - api_key, api_secret; Cloudflare - account_id, api_key (API token).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RegistrarAccountConfig {
+    // Empty - provider of registrar of account.
+    #[serde(default)]
+    pub provider: Option<Provider>,
+
+    // Base url of API, empty - url of provider. For tests with mock server.
+    #[serde(default)]
+    pub api_url: String,
+
+    #[serde(default)]
+    pub login: String,
+
+    #[serde(default)]
+    pub password: String,
+
+    #[serde(default)]
+    pub password_file: String,
+
+    #[serde(default)]
+    pub api_key: String,
+
+    #[serde(default)]
+    pub api_key_file: String,
+
+    #[serde(default)]
+    pub api_secret: String,
+
+    #[serde(default)]
+    pub api_secret_file: String,
+
+    // Whitelisted address of monitoring host, for Namecheap.
+    #[serde(default)]
+    pub client_ip: String,
+
+    #[serde(default)]
+    pub account_id: String,
+
+    // Proxy url like whois_proxy, empty - connect directly.
+    #[serde(default)]
+    pub proxy: String,
+
+    // Zone -> renewal price, overrides renewal_prices of config.
+    #[serde(default)]
+    pub renewal_prices: HashMap<String, f64>,
+}
+
+// Account from config with known provider.
+pub(crate) struct RegistrarAccount {
+    pub provider: Provider,
+    pub config: RegistrarAccountConfig,
+}
+
+impl RegistrarAccount {
+    fn api_url(&self) -> String {
+        let url = if self.config.api_url.is_empty() {
+            self.provider.default_api_url()
+        } else {
+            &self.config.api_url
+        };
+        return url.trim_end_matches('/').to_string();
+    }
+
+    // Credentials, required by provider, are set.
+    pub(crate) fn check_credentials(&self) -> std::result::Result<(), String> {
+        use Provider::*;
+        let required = match self.provider {
+            RegRu | NicRu => vec![
+                ("login", &self.config.login),
+                ("password", &self.config.password),
+            ],
+            Namecheap => vec![
+                ("login", &self.config.login),
+                ("api_key", &self.config.api_key),
+                ("client_ip", &self.config.client_ip),
+            ],
+            GoDaddy => vec![
+                ("api_key", &self.config.api_key),
+                ("api_secret", &self.config.api_secret),
+            ],
+            Cloudflare => vec![
+                ("account_id", &self.config.account_id),
+                ("api_key", &self.config.api_key),
+            ],
+        };
+        let missed: Vec<&str> = required
+            .into_iter()
+            .filter(|(_, value)| value.is_empty())
+            .map(|(name, _)| name)
+            .collect();
+        if !missed.is_empty() {
+            return Err(format!(
+                "{} required for {} API",
+                missed.join(", "),
+                self.provider
+            ));
+        }
+        return Ok(());
+    }
+
+    fn route(&self) -> Route {
+        let mut route = Route::direct();
+        route.proxy = self.config.proxy.clone();
+        return route;
+    }
+}
+
+// Registration of domain, reported by registrar.
+pub(crate) struct DomainInfo {
+    pub expire: DateTime<Utc>,
+
+    // None - registrar doesn't report autorenew.
+    pub autorenew: Option<bool>,
+
+    // Raw API response for archive.
+    pub raw: String,
+}
+
+pub(crate) struct Balance {
+    pub amount: f64,
+    pub currency: String,
+}
+
+// Registrar accounts from config: credentials_ref or registrar/login of account -> credentials.
+pub(crate) struct Registrars {
+    accounts: HashMap<String, RegistrarAccountConfig>,
+}
+
+impl Registrars {
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        return Registrars::new(&cfg.registrar_accounts);
+    }
+
+    // Keys are case insensitive, like keys of config.
+    fn new(accounts: &HashMap<String, RegistrarAccountConfig>) -> Self {
+        return Registrars {
+            accounts: accounts
+                .iter()
+                .map(|(key, config)| (key.to_lowercase(), config.clone()))
+                .collect(),
+        };
+    }
+
+    // Credentials of account are in config.
+    pub(crate) fn contains(&self, account: &AccountConfig) -> bool {
+        return self.accounts.contains_key(&account.credentials_key());
+    }
+
+    // Credentials of account of domain. Provider is taken from credentials,
+    // registrar of account or registrar part of credentials key.
+    pub(crate) fn account(&self, account: &AccountConfig) -> Option<RegistrarAccount> {
+        let key = account.credentials_key();
+        let config = self.accounts.get(&key)?;
+        let provider = config
+            .provider
+            .or_else(|| Provider::from_registrar(&account.registrar))
+            .or_else(|| key_provider(&key))?;
+        return Some(RegistrarAccount {
+            provider,
+            config: config.clone(),
+        });
+    }
+
+    // Accounts sorted by key. Error if provider of account is unknown.
+    pub(crate) fn accounts(&self) -> Vec<(&String, std::result::Result<RegistrarAccount, String>)> {
+        let mut res: Vec<_> = self
+            .accounts
+            .iter()
+            .map(|(key, config)| (key, resolve(key, config)))
+            .collect();
+        res.sort_by(|(key1, _), (key2, _)| key1.cmp(key2));
+        return res;
+    }
+}
+
+// Provider by registrar part of key: reg.ru/login -> RegRu.
+fn key_provider(key: &str) -> Option<Provider> {
+    return Provider::from_registrar(key.splitn(2, '/').next().unwrap_or(""));
+}
+
+// Account from config without domain: provider is from config or key.
+pub(crate) fn resolve(
+    key: &str,
+    config: &RegistrarAccountConfig,
+) -> std::result::Result<RegistrarAccount, String> {
+    let provider = config
+        .provider
+        .or_else(|| key_provider(key))
+        .ok_or_else(|| {
+            "provider is required: it isn't known registrar in registrar/login key".to_string()
+        })?;
+    return Ok(RegistrarAccount {
+        provider,
+        config: config.clone(),
+    });
+}
+
+// Expire date and autorenew of ascii domain name from registrar API.
+pub(crate) fn domain_info(account: &RegistrarAccount, domain: &str) -> Result<DomainInfo> {
+    use Provider::*;
+    match account.provider {
+        RegRu => return regru_domain_info(account, domain),
+        NicRu => return nicru_domain_info(account, domain),
+        Namecheap => return namecheap_domain_info(account, domain),
+        GoDaddy => return godaddy_domain_info(account, domain),
+        Cloudflare => return cloudflare_domain_info(account, domain),
+    }
+}
+
+// Balance of account, None if registrar API doesn't report balance.
+pub(crate) fn balance(account: &RegistrarAccount) -> Result<Option<Balance>> {
+    use Provider::*;
+    match account.provider {
+        RegRu => return regru_balance(account).map(Some),
+        Namecheap => return namecheap_balance(account).map(Some),
+        NicRu | GoDaddy | Cloudflare => return Ok(None),
+    }
+}
+
+// Ascii names of all domains of account from registrar API.
+pub(crate) fn domains(account: &RegistrarAccount) -> Result<Vec<String>> {
+    use Provider::*;
+    match account.provider {
+        RegRu => return regru_domains(account),
+        NicRu => return nicru_domains(account),
+        Namecheap => return namecheap_domains(account),
+        GoDaddy => return godaddy_domains(account),
+        Cloudflare => return cloudflare_domains(account),
+    }
+}
+
+fn api_error(provider: Provider, message: &str) -> Error {
+    return Error::RegistrarError(format!("{} API: {}", provider, message));
+}
+
+fn get(account: &RegistrarAccount, url: &str, headers: &[(&str, String)]) -> Result<HttpResponse> {
+    return net::http_request(&account.route(), "GET", url, headers, "");
+}
+
+fn parse_json(provider: Provider, res: &HttpResponse) -> Result<json::JsonValue> {
+    return json::parse(&res.body).map_err(|err| {
+        api_error(
+            provider,
+            &format!("bad response with status {}: {}", res.status, err),
+        )
+    });
+}
+
+// RFC 3339 or date only.
+fn parse_date(provider: Provider, date: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    for format in &["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(date, format) {
+            return Ok(Utc.from_utc_datetime(&date.and_hms(0, 0, 0)));
+        }
+    }
+    return Err(api_error(provider, &format!("bad date {}", date)));
+}
+
+// Answer of reg.ru API or error: {"result": "success", "answer": {...}}.
+// Credentials are sent in POST form, so they aren't in urls of logs and errors.
+fn regru_request(
+    account: &RegistrarAccount,
+    method: &str,
+    input: Option<json::JsonValue>,
+) -> Result<(json::JsonValue, String)> {
+    let url = format!("{}/{}", account.api_url(), method);
+    let mut form = url::form_urlencoded::Serializer::new(String::new());
+    form.append_pair("username", &account.config.login)
+        .append_pair("password", &account.config.password);
+    if let Some(input) = input {
+        form.append_pair("input_format", "json")
+            .append_pair("input_data", &input.dump());
+    }
+    let res = net::http_request(
+        &account.route(),
+        "POST",
+        &url,
+        &[(
+            "Content-Type",
+            "application/x-www-form-urlencoded".to_string(),
+        )],
+        &form.finish(),
+    )?;
+    let mut value = parse_json(account.provider, &res)?;
+    if value["result"].as_str() != Some("success") {
+        return Err(api_error(
+            account.provider,
+            value["error_text"].as_str().unwrap_or("unknown error"),
+        ));
+    }
+    return Ok((value["answer"].take(), res.body));
+}
+
+fn regru_domain_info(account: &RegistrarAccount, domain: &str) -> Result<DomainInfo> {
+    let mut item = json::JsonValue::new_object();
+    item["dname"] = domain.into();
+    let mut input = json::JsonValue::new_object();
+    input["domains"] = vec![item].into();
+    let (answer, raw) = regru_request(account, "service/get_info", Some(input))?;
+    let service = &answer["services"][0];
+    if let Some(error) = service["error_text"].as_str() {
+        return Err(api_error(account.provider, error));
+    }
+    let expire = service["expiration_date"]
+        .as_str()
+        .ok_or_else(|| api_error(account.provider, "no expiration_date in answer"))?;
+    return Ok(DomainInfo {
+        expire: parse_date(account.provider, expire)?,
+        autorenew: None,
+        raw,
+    });
+}
+
+fn regru_domains(account: &RegistrarAccount) -> Result<Vec<String>> {
+    let mut input = json::JsonValue::new_object();
+    input["servtype"] = "domain".into();
+    let (answer, _) = regru_request(account, "service/get_list", Some(input))?;
+    return Ok(answer["services"]
+        .members()
+        .filter_map(|service| service["dname"].as_str())
+        .map(|name| name.to_lowercase())
+        .collect());
+}
+
+fn regru_balance(account: &RegistrarAccount) -> Result<Balance> {
+    let (answer, _) = regru_request(account, "user/get_balance", None)?;
+    // Amount is string or number.
+    let amount = match answer["prepay"].as_f64() {
+        Some(amount) => Some(amount),
+        None => answer["prepay"]
+            .as_str()
+            .and_then(|amount| amount.parse().ok()),
+    };
+    return Ok(Balance {
+        amount: amount.ok_or_else(|| api_error(account.provider, "no prepay in answer"))?,
+        currency: answer["currency"].as_str().unwrap_or("").to_string(),
+    });
+}
+
+// Sections of nic.ru partner API response: "[name]" header and "key:value" lines.
+// Request is text form of the API: header lines, empty line and request section.
+fn nicru_request(
+    account: &RegistrarAccount,
+    request: &str,
+    operation: &str,
+    section: &[(&str, &str)],
+) -> Result<(Vec<(String, HashMap<String, String>)>, String)> {
+    let mut text = format!(
+        "lang:en\r\nrequest:{}\r\noperation:{}\r\nlogin:{}\r\npassword:{}\r\nrequest-id:{}@whois-monitoring\r\n",
+        request,
+        operation,
+        account.config.login,
+        account.config.password,
+        Utc::now().timestamp_nanos()
+    );
+    if !section.is_empty() {
+        text += &format!("\r\n[{}]\r\n", request);
+        for (key, value) in section {
+            text += &format!("{}:{}\r\n", key, value);
+        }
+    }
+    let body: String = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("SimpleRequest", &text)
+        .finish();
+    let res = net::http_request(
+        &account.route(),
+        "POST",
+        &account.api_url(),
+        &[(
+            "Content-Type",
+            "application/x-www-form-urlencoded".to_string(),
+        )],
+        &body,
+    )?;
+
+    // First line: "State: 200 OK" or error code and message.
+    let state = res.body.lines().next().unwrap_or("").trim();
+    let state = state.splitn(2, ':').nth(1).unwrap_or("").trim();
+    if !state.starts_with("200") {
+        let message = if state.is_empty() {
+            format!("bad response with status {}", res.status)
+        } else {
+            state.to_string()
+        };
+        return Err(api_error(account.provider, &message));
+    }
+    let mut sections = Vec::new();
+    for line in res.body.lines().skip(1) {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            sections.push((line[1..line.len() - 1].to_string(), HashMap::new()));
+            continue;
+        }
+        let mut parts = line.splitn(2, ':');
+        if let (Some((_, fields)), Some(key), Some(value)) =
+            (sections.last_mut(), parts.next(), parts.next())
+        {
+            fields
+                .entry(key.trim().to_lowercase())
+                .or_insert_with(|| value.trim().to_string());
+        }
+    }
+    return Ok((sections, res.body));
+}
+
+// Domain services of nic.ru account.
+fn nicru_services(
+    account: &RegistrarAccount,
+    domain: Option<&str>,
+) -> Result<(Vec<HashMap<String, String>>, String)> {
+    let mut section = vec![("service", "domain")];
+    if let Some(domain) = domain {
+        section.push(("domain", domain));
+    }
+    let (sections, raw) = nicru_request(account, "service", "search", &section)?;
+    let services = sections
+        .into_iter()
+        .filter(|(name, _)| name == "service")
+        .map(|(_, fields)| fields)
+        .filter(|fields| fields.contains_key("domain"))
+        .collect();
+    return Ok((services, raw));
+}
+
+fn nicru_domain_info(account: &RegistrarAccount, domain: &str) -> Result<DomainInfo> {
+    let (services, raw) = nicru_services(account, Some(domain))?;
+    let service = services
+        .iter()
+        .find(|fields| fields["domain"].to_lowercase() == domain)
+        .ok_or_else(|| api_error(account.provider, "domain isn't found in account"))?;
+    let expire = service
+        .get("payed-till")
+        .ok_or_else(|| api_error(account.provider, "no payed-till in answer"))?;
+    return Ok(DomainInfo {
+        expire: parse_date(account.provider, expire)?,
+        autorenew: service.get("autoprolong").map(|value| value == "1"),
+        raw,
+    });
+}
+
+fn nicru_domains(account: &RegistrarAccount) -> Result<Vec<String>> {
+    let (services, _) = nicru_services(account, None)?;
+    return Ok(services
+        .iter()
+        .map(|fields| fields["domain"].to_lowercase())
+        .collect());
+}
+
+// XML response of Namecheap API, checked for errors.
+fn namecheap_request(
+    account: &RegistrarAccount,
+    command: &str,
+    params: &[(&str, &str)],
+) -> Result<String> {
+    let mut url = url::Url::parse(&account.api_url())
+        .map_err(|err| api_error(account.provider, &err.to_string()))?;
+    url.query_pairs_mut()
+        .append_pair("ApiUser", &account.config.login)
+        .append_pair("ApiKey", &account.config.api_key)
+        .append_pair("UserName", &account.config.login)
+        .append_pair("ClientIp", &account.config.client_ip)
+        .append_pair("Command", command)
+        .extend_pairs(params);
+    let res = get(account, url.as_str(), &[])?;
+    let status = xml_elements(&res.body, "ApiResponse")
+        .into_iter()
+        .next()
+        .and_then(|attrs| attrs.get("Status").cloned());
+    if status.as_ref().map(String::as_str) != Some("OK") {
+        let message = xml_text(&res.body, "Error")
+            .unwrap_or_else(|| format!("bad response with status {}", res.status));
+        return Err(api_error(account.provider, &message));
+    }
+    return Ok(res.body);
+}
+
+fn namecheap_domain_info(account: &RegistrarAccount, domain: &str) -> Result<DomainInfo> {
+    let raw = namecheap_request(
+        account,
+        "namecheap.domains.getList",
+        &[("SearchTerm", domain), ("PageSize", "100")],
+    )?;
+    let item = xml_elements(&raw, "Domain")
+        .into_iter()
+        .find(|attrs| attrs.get("Name").map(|name| name.to_lowercase()) == Some(domain.to_string()))
+        .ok_or_else(|| api_error(account.provider, "domain isn't found in account"))?;
+    let expire = item
+        .get("Expires")
+        .ok_or_else(|| api_error(account.provider, "no Expires in answer"))?;
+    return Ok(DomainInfo {
+        expire: parse_date(account.provider, expire)?,
+        autorenew: item.get("AutoRenew").map(|value| value == "true"),
+        raw,
+    });
+}
+
+// All pages of domains list.
+fn namecheap_domains(account: &RegistrarAccount) -> Result<Vec<String>> {
+    let mut res = Vec::new();
+    for page in 1.. {
+        let raw = namecheap_request(
+            account,
+            "namecheap.domains.getList",
+            &[("Page", &page.to_string()), ("PageSize", "100")],
+        )?;
+        let names: Vec<String> = xml_elements(&raw, "Domain")
+            .into_iter()
+            .filter_map(|attrs| attrs.get("Name").map(|name| name.to_lowercase()))
+            .collect();
+        let total: usize = xml_text(&raw, "TotalItems")
+            .and_then(|total| total.parse().ok())
+            .unwrap_or(0);
+        let is_last = names.is_empty();
+        res.extend(names);
+        if is_last || res.len() >= total {
+            break;
+        }
+    }
+    return Ok(res);
+}
+
+fn namecheap_balance(account: &RegistrarAccount) -> Result<Balance> {
+    let raw = namecheap_request(account, "namecheap.users.getBalances", &[])?;
+    let attrs = xml_elements(&raw, "UserGetBalancesResult")
+        .into_iter()
+        .next()
+        .ok_or_else(|| api_error(account.provider, "no balances in answer"))?;
+    let amount = attrs
+        .get("AvailableBalance")
+        .and_then(|amount| amount.parse().ok())
+        .ok_or_else(|| api_error(account.provider, "no AvailableBalance in answer"))?;
+    return Ok(Balance {
+        amount,
+        currency: attrs.get("Currency").cloned().unwrap_or_default(),
+    });
+}
+
+fn godaddy_domain_info(account: &RegistrarAccount, domain: &str) -> Result<DomainInfo> {
+    let url = format!("{}/v1/domains/{}", account.api_url(), domain);
+    let auth = format!(
+        "sso-key {}:{}",
+        account.config.api_key, account.config.api_secret
+    );
+    let res = get(account, &url, &[("Authorization", auth)])?;
+    let value = parse_json(account.provider, &res)?;
+    if res.status != 200 {
+        return Err(api_error(
+            account.provider,
+            value["message"].as_str().unwrap_or("unknown error"),
+        ));
+    }
+    let expire = value["expires"]
+        .as_str()
+        .ok_or_else(|| api_error(account.provider, "no expires in answer"))?;
+    return Ok(DomainInfo {
+        expire: parse_date(account.provider, expire)?,
+        autorenew: value["renewAuto"].as_bool(),
+        raw: res.body,
+    });
+}
+
+// Pages of domains list: marker is last domain of previous page.
+fn godaddy_domains(account: &RegistrarAccount) -> Result<Vec<String>> {
+    const LIMIT: usize = 1000;
+    let auth = format!(
+        "sso-key {}:{}",
+        account.config.api_key, account.config.api_secret
+    );
+    let mut res: Vec<String> = Vec::new();
+    loop {
+        let mut url = format!("{}/v1/domains?limit={}", account.api_url(), LIMIT);
+        if let Some(marker) = res.last() {
+            url += &format!("&marker={}", marker);
+        }
+        let page = get(account, &url, &[("Authorization", auth.clone())])?;
+        let value = parse_json(account.provider, &page)?;
+        if page.status != 200 {
+            return Err(api_error(
+                account.provider,
+                value["message"].as_str().unwrap_or("unknown error"),
+            ));
+        }
+        let names: Vec<String> = value
+            .members()
+            .filter_map(|item| item["domain"].as_str())
+            .map(|name| name.to_lowercase())
+            .collect();
+        let is_last = names.len() < LIMIT;
+        res.extend(names);
+        if is_last {
+            return Ok(res);
+        }
+    }
+}
+
+fn cloudflare_domain_info(account: &RegistrarAccount, domain: &str) -> Result<DomainInfo> {
+    let url = format!(
+        "{}/accounts/{}/registrar/domains/{}",
+        account.api_url(),
+        account.config.account_id,
+        domain
+    );
+    let auth = format!("Bearer {}", account.config.api_key);
+    let res = get(account, &url, &[("Authorization", auth)])?;
+    let value = parse_json(account.provider, &res)?;
+    if value["success"].as_bool() != Some(true) {
+        return Err(api_error(
+            account.provider,
+            value["errors"][0]["message"]
+                .as_str()
+                .unwrap_or("unknown error"),
+        ));
+    }
+    let expire = value["result"]["expires_at"]
+        .as_str()
+        .ok_or_else(|| api_error(account.provider, "no expires_at in answer"))?;
+    return Ok(DomainInfo {
+        expire: parse_date(account.provider, expire)?,
+        autorenew: value["result"]["auto_renew"].as_bool(),
+        raw: res.body,
+    });
+}
+
+// All pages of domains list: result_info has number of pages.
+fn cloudflare_domains(account: &RegistrarAccount) -> Result<Vec<String>> {
+    let auth = format!("Bearer {}", account.config.api_key);
+    let mut res = Vec::new();
+    for page in 1.. {
+        let url = format!(
+            "{}/accounts/{}/registrar/domains?page={}&per_page=50",
+            account.api_url(),
+            account.config.account_id,
+            page
+        );
+        let res_page = get(account, &url, &[("Authorization", auth.clone())])?;
+        let value = parse_json(account.provider, &res_page)?;
+        if value["success"].as_bool() != Some(true) {
+            return Err(api_error(
+                account.provider,
+                value["errors"][0]["message"]
+                    .as_str()
+                    .unwrap_or("unknown error"),
+            ));
+        }
+        let names: Vec<String> = value["result"]
+            .members()
+            .filter_map(|item| item["name"].as_str())
+            .map(|name| name.to_lowercase())
+            .collect();
+        let total_pages = value["result_info"]["total_pages"].as_u64().unwrap_or(0);
+        let is_last = names.is_empty();
+        res.extend(names);
+        if is_last || page >= total_pages {
+            break;
+        }
+    }
+    return Ok(res);
+}
+
+// Balances of all accounts. Errors are printed in balance column.
+pub(crate) fn create_balance_report(log: &slog::Logger, registrars: &Registrars) -> String {
+    let mut table = vec![];
+    for (name, account) in registrars.accounts() {
+        let account = match account {
+            Ok(account) => account,
+            Err(err) => {
+                table.push([name.clone(), String::new(), err]);
+                continue;
+            }
+        };
+        let balance = match balance(&account).log(log, Level::Error) {
+            Ok(Some(balance)) => format!("{:.2} {}", balance.amount, balance.currency),
+            Ok(None) => "unknown".to_string(),
+            Err(err) => err.to_string(),
+        };
+        table.push([name.clone(), account.provider.to_string(), balance]);
+    }
+
+    let mut account_column = ascii_table::ColumnConfig::default();
+    account_column.header = "Account".to_string();
+
+    let mut provider_column = ascii_table::ColumnConfig::default();
+    provider_column.header = "Provider".to_string();
+
+    let mut balance_column = ascii_table::ColumnConfig::default();
+    balance_column.header = "Balance".to_string();
+
+    let mut table_config = ascii_table::TableConfig::default();
+    table_config.width = 140;
+    table_config.columns.insert(0, account_column);
+    table_config.columns.insert(1, provider_column);
+    table_config.columns.insert(2, balance_column);
+    return ascii_table::format_table(&table, &table_config);
+}
+
+// Attributes of elements <tag .../> or <tag ...>. Enough for flat answers of Namecheap API.
+fn xml_elements(xml: &str, tag: &str) -> Vec<HashMap<String, String>> {
+    return xml_tags(xml, tag)
+        .into_iter()
+        .map(|(attributes, _)| xml_attributes(attributes))
+        .collect();
+}
+
+// Attributes text of opening tags and text after them.
+fn xml_tags<'a>(xml: &'a str, tag: &str) -> Vec<(&'a str, &'a str)> {
+    let mut res = Vec::new();
+    let open = format!("<{}", tag);
+    let mut rest = xml;
+    while let Some(index) = rest.find(&open) {
+        rest = &rest[index + open.len()..];
+        // Skip tags with same prefix: <Domain> and <DomainGetListResult>.
+        if !rest.starts_with(|c: char| c.is_whitespace() || c == '>' || c == '/') {
+            continue;
+        }
+        let end = rest.find('>').unwrap_or_else(|| rest.len());
+        let after = if end < rest.len() { end + 1 } else { end };
+        res.push((&rest[..end], &rest[after..]));
+        rest = &rest[end..];
+    }
+    return res;
+}
+
+fn xml_attributes(mut text: &str) -> HashMap<String, String> {
+    let mut res = HashMap::new();
+    while let Some(eq) = text.find("=\"") {
+        let name = text[..eq].trim().to_string();
+        let value_start = eq + 2;
+        let value_end = match text[value_start..].find('"') {
+            Some(index) => value_start + index,
+            None => break,
+        };
+        res.insert(name, xml_unescape(&text[value_start..value_end]));
+        text = &text[value_end + 1..];
+    }
+    return res;
+}
+
+// Text of first element <tag ...>text</tag>, empty elements <tag .../> are skipped.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let (_, text) = xml_tags(xml, tag)
+        .into_iter()
+        .find(|(attributes, _)| !attributes.ends_with('/'))?;
+    let end = text.find(&format!("</{}>", tag))?;
+    return Some(xml_unescape(text[..end].trim()));
+}
+
+fn xml_unescape(text: &str) -> String {
+    return text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            io::{BufRead, BufReader, Read, Write},
+            net::TcpListener,
+            sync::{Arc, Mutex},
+            thread,
+        },
+    };
+
+    // Request, received by mock server.
+    #[derive(Debug, Clone)]
+    struct Request {
+        method: String,
+        target: String,
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    impl Request {
+        fn form(&self) -> HashMap<String, String> {
+            return url::form_urlencoded::parse(self.body.as_bytes())
+                .into_owned()
+                .collect();
+        }
+
+        fn query(&self) -> HashMap<String, String> {
+            let query = self.target.splitn(2, '?').nth(1).unwrap_or("");
+            return url::form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect();
+        }
+    }
+
+    type Requests = Arc<Mutex<Vec<Request>>>;
+
+    // Mock of registrar API: responds with status and body by request.
+    // Returns base url and received requests.
+    fn start_api<F>(respond: F) -> (String, Requests)
+    where
+        F: Fn(&Request) -> (u16, String) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+        thread::spawn(move || {
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                let mut reader = BufReader::new(conn.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or("").to_string();
+                let target = parts.next().unwrap_or("").to_string();
+                let mut headers = HashMap::new();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let mut parts = line.trim_end().splitn(2, ':');
+                    match (parts.next(), parts.next()) {
+                        (Some(name), Some(value)) => {
+                            headers.insert(name.to_lowercase(), value.trim().to_string())
+                        }
+                        _ => break,
+                    };
+                }
+                let length = headers
+                    .get("content-length")
+                    .and_then(|length| length.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0u8; length];
+                reader.read_exact(&mut body).unwrap();
+                let request = Request {
+                    method,
+                    target,
+                    headers,
+                    body: String::from_utf8(body).unwrap(),
+                };
+                let (status, body) = respond(&request);
+                received.lock().unwrap().push(request);
+                let response = format!("HTTP/1.0 {} Mock\r\n\r\n{}", status, body);
+                conn.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        return (url, requests);
+    }
+
+    fn account(provider: &str, api_url: &str) -> RegistrarAccount {
+        let config: RegistrarAccountConfig = serde_yaml::from_str(&format!(
+            "
+provider: {}
+api_url: {}
+login: user
+password: secret
+api_key: key
+api_secret: key-secret
+client_ip: 192.0.2.1
+account_id: acc
+",
+            provider, api_url
+        ))
+        .unwrap();
+        return resolve("test", &config).unwrap();
+    }
+
+    fn date(date: &str) -> DateTime<Utc> {
+        return DateTime::parse_from_rfc3339(date)
+            .unwrap()
+            .with_timezone(&Utc);
+    }
+
+    fn error_text<T>(res: Result<T>) -> String {
+        match res {
+            Ok(_) => panic!("error expected"),
+            Err(err) => return err.to_string(),
+        }
+    }
+
+    #[test]
+    fn regru_domain_info_and_balance() {
+        let (url, requests) = start_api(|request| {
+            let body = if request.target.ends_with("service/get_info") {
+                r#"{"result": "success", "answer": {"services": [
+                    {"dname": "example.ru", "expiration_date": "2027-07-07", "servtype": "domain"}
+                ]}}"#
+            } else {
+                r#"{"result": "success", "answer": {"prepay": "1234.5", "currency": "RUR"}}"#
+            };
+            return (200, body.to_string());
+        });
+        let account = account("RegRu", &url);
+        let info = domain_info(&account, "example.ru").unwrap();
+        assert_eq!(info.expire, date("2027-07-07T00:00:00Z"));
+        assert_eq!(info.autorenew, None);
+        assert!(info.raw.contains("expiration_date"));
+
+        let balance = balance(&account).unwrap().unwrap();
+        assert_eq!(balance.amount, 1234.5);
+        assert_eq!(balance.currency, "RUR");
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].target, "/service/get_info");
+        assert_eq!(
+            requests[0].headers["content-type"],
+            "application/x-www-form-urlencoded"
+        );
+        let form = requests[0].form();
+        assert_eq!(form["username"], "user");
+        assert_eq!(form["password"], "secret");
+        assert_eq!(form["input_format"], "json");
+        let input = json::parse(&form["input_data"]).unwrap();
+        assert_eq!(input["domains"][0]["dname"], "example.ru");
+        assert_eq!(requests[1].target, "/user/get_balance");
+    }
+
+    #[test]
+    fn regru_errors() {
+        let (url, _) = start_api(|request| {
+            let body = if request.target.ends_with("service/get_info") {
+                r#"{"result": "success", "answer": {"services": [
+                    {"dname": "example.ru", "error_code": "DOMAIN_NOT_FOUND", "error_text": "Domain not found"}
+                ]}}"#
+            } else {
+                r#"{"result": "error", "error_code": "PASSWORD_AUTH_FAILED", "error_text": "Username/password Incorrect"}"#
+            };
+            return (200, body.to_string());
+        });
+        let account = account("RegRu", &url);
+        assert_eq!(
+            error_text(domain_info(&account, "example.ru")),
+            "reg.ru API: Domain not found"
+        );
+        assert_eq!(
+            error_text(balance(&account)),
+            "reg.ru API: Username/password Incorrect"
+        );
+    }
+
+    #[test]
+    fn bad_json() {
+        let (url, _) = start_api(|_| (502, "Bad gateway".to_string()));
+        let err = error_text(balance(&account("RegRu", &url)));
+        assert!(err.starts_with("reg.ru API: bad response with status 502"));
+    }
+
+    #[test]
+    fn nicru_domain_info_and_domains() {
+        let (url, requests) = start_api(|request| {
+            let text = &request.form()["SimpleRequest"];
+            if !text.contains("password:secret") {
+                return (200, "State: 402 Authorization failed\r\n".to_string());
+            }
+            let body = if text.contains("domain:example.ru") {
+                "State: 200 OK\r\n\r\n[service]\r\nservice-id:1\r\nservice:domain\r\ndomain:EXAMPLE.RU\r\npayed-till:2027-05-05\r\nautoprolong:1\r\n"
+            } else {
+                "State: 200 OK\r\n\r\n[service]\r\ndomain:example.ru\r\npayed-till:2027-05-05\r\n\r\n[service]\r\ndomain:xn--e1afmkfd.xn--p1ai\r\npayed-till:06.06.2027\r\n"
+            };
+            return (200, body.to_string());
+        });
+        let account = account("NicRu", &url);
+        let info = domain_info(&account, "example.ru").unwrap();
+        assert_eq!(info.expire, date("2027-05-05T00:00:00Z"));
+        assert_eq!(info.autorenew, Some(true));
+        assert!(info.raw.contains("payed-till"));
+        assert!(balance(&account).unwrap().is_none());
+        assert_eq!(
+            domains(&account).unwrap(),
+            vec!["example.ru", "xn--e1afmkfd.xn--p1ai"]
+        );
+
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.target, "/");
+        let text = &request.form()["SimpleRequest"];
+        assert!(text.contains("request:service\r\noperation:search\r\nlogin:user\r\n"));
+        assert!(text.contains("\r\n[service]\r\nservice:domain\r\ndomain:example.ru\r\n"));
+    }
+
+    #[test]
+    fn nicru_errors() {
+        let (url, _) = start_api(|request| {
+            if request.form()["SimpleRequest"].contains("domain:other.ru") {
+                return (200, "State: 200 OK\r\n".to_string());
+            }
+            return (200, "State: 402 Authorization failed\r\n".to_string());
+        });
+        let account = account("NicRu", &url);
+        assert_eq!(
+            error_text(domain_info(&account, "other.ru")),
+            "nic.ru API: domain isn't found in account"
+        );
+        assert_eq!(
+            error_text(domain_info(&account, "example.ru")),
+            "nic.ru API: 402 Authorization failed"
+        );
+    }
+
+    #[test]
+    fn namecheap_domain_info_and_balance() {
+        let (url, requests) = start_api(|request| {
+            let body = match request.query()["Command"].as_str() {
+                "namecheap.domains.getList" => {
+                    r#"<?xml version="1.0" encoding="utf-8"?>
+<ApiResponse Status="OK" xmlns="http://api.namecheap.com/xml.response">
+  <Errors />
+  <CommandResponse Type="namecheap.domains.getList">
+    <DomainGetListResult>
+      <Domain ID="1" Name="sub-example.com" Expires="01/01/2030" AutoRenew="false" />
+      <Domain ID="2" Name="Example.com" User="user" Created="08/09/2020" Expires="08/09/2027" IsExpired="false" AutoRenew="true" />
+    </DomainGetListResult>
+  </CommandResponse>
+</ApiResponse>"#
+                }
+                _ => {
+                    r#"<ApiResponse Status="OK"><CommandResponse Type="namecheap.users.getBalances">
+<UserGetBalancesResult Currency="USD" AvailableBalance="31.02" AccountBalance="31.02" /></CommandResponse></ApiResponse>"#
+                }
+            };
+            return (200, body.to_string());
+        });
+        let account = account("Namecheap", &url);
+        let info = domain_info(&account, "example.com").unwrap();
+        assert_eq!(info.expire, date("2027-08-09T00:00:00Z"));
+        assert_eq!(info.autorenew, Some(true));
+
+        let balance = balance(&account).unwrap().unwrap();
+        assert_eq!(balance.amount, 31.02);
+        assert_eq!(balance.currency, "USD");
+
+        let query = requests.lock().unwrap()[0].query();
+        assert_eq!(query["ApiUser"], "user");
+        assert_eq!(query["ApiKey"], "key");
+        assert_eq!(query["ClientIp"], "192.0.2.1");
+        assert_eq!(query["SearchTerm"], "example.com");
+    }
+
+    #[test]
+    fn namecheap_errors() {
+        let (url, _) = start_api(|request| {
+            let body = match request.query()["Command"].as_str() {
+                "namecheap.domains.getList" => {
+                    r#"<ApiResponse Status="OK"><CommandResponse><DomainGetListResult>
+<Domain ID="1" Name="other.com" Expires="01/01/2030" /></DomainGetListResult></CommandResponse></ApiResponse>"#
+                }
+                _ => {
+                    r#"<ApiResponse Status="ERROR"><Errors><Error Number="1011102">API Key is invalid &amp; wrong</Error></Errors></ApiResponse>"#
+                }
+            };
+            return (200, body.to_string());
+        });
+        let account = account("Namecheap", &url);
+        assert_eq!(
+            error_text(domain_info(&account, "example.com")),
+            "namecheap API: domain isn't found in account"
+        );
+        assert_eq!(
+            error_text(balance(&account)),
+            "namecheap API: API Key is invalid & wrong"
+        );
+    }
+
+    #[test]
+    fn godaddy_domain_info() {
+        let (url, requests) = start_api(|request| {
+            if request.target.ends_with("/example.com") {
+                return (
+                    200,
+                    r#"{"domain": "example.com", "expires": "2027-10-10T12:00:00.000Z", "renewAuto": false}"#
+                        .to_string(),
+                );
+            }
+            return (
+                404,
+                r#"{"code": "NOT_FOUND", "message": "Domain not found"}"#.to_string(),
+            );
+        });
+        let account = account("GoDaddy", &url);
+        let info = domain_info(&account, "example.com").unwrap();
+        assert_eq!(info.expire, date("2027-10-10T12:00:00Z"));
+        assert_eq!(info.autorenew, Some(false));
+        assert_eq!(
+            error_text(domain_info(&account, "other.com")),
+            "godaddy API: Domain not found"
+        );
+        assert!(balance(&account).unwrap().is_none());
+
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.target, "/v1/domains/example.com");
+        assert_eq!(request.headers["authorization"], "sso-key key:key-secret");
+    }
+
+    #[test]
+    fn cloudflare_domain_info() {
+        let (url, requests) = start_api(|request| {
+            if request.target.ends_with("/example.com") {
+                return (
+                    200,
+                    r#"{"success": true, "errors": [], "result": {"name": "example.com", "expires_at": "2027-11-11T23:59:59Z", "auto_renew": true}}"#
+                        .to_string(),
+                );
+            }
+            return (
+                403,
+                r#"{"success": false, "errors": [{"code": 10000, "message": "Authentication error"}]}"#
+                    .to_string(),
+            );
+        });
+        let account = account("Cloudflare", &url);
+        let info = domain_info(&account, "example.com").unwrap();
+        assert_eq!(info.expire, date("2027-11-11T23:59:59Z"));
+        assert_eq!(info.autorenew, Some(true));
+        assert_eq!(
+            error_text(domain_info(&account, "other.com")),
+            "cloudflare API: Authentication error"
+        );
+        assert!(balance(&account).unwrap().is_none());
+
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(
+            request.target,
+            "/accounts/acc/registrar/domains/example.com"
+        );
+        assert_eq!(request.headers["authorization"], "Bearer key");
+    }
+
+    #[test]
+    fn regru_domains_list() {
+        let (url, requests) = start_api(|_| {
+            return (
+                200,
+                r#"{"result": "success", "answer": {"services": [
+                    {"dname": "Example.ru", "servtype": "domain"},
+                    {"dname": "xn--e1afmkfd.xn--p1ai", "servtype": "domain"}
+                ]}}"#
+                    .to_string(),
+            );
+        });
+        let account = account("RegRu", &url);
+        assert_eq!(
+            domains(&account).unwrap(),
+            vec!["example.ru", "xn--e1afmkfd.xn--p1ai"]
+        );
+        let request = requests.lock().unwrap()[0].clone();
+        assert_eq!(request.target, "/service/get_list");
+        assert_eq!(request.form()["input_data"], r#"{"servtype":"domain"}"#);
+    }
+
+    #[test]
+    fn namecheap_domains_pages() {
+        let (url, requests) = start_api(|request| {
+            let body = match request.query()["Page"].as_str() {
+                "1" => {
+                    r#"<ApiResponse Status="OK"><CommandResponse><DomainGetListResult>
+<Domain ID="1" Name="one.com" /><Domain ID="2" Name="Two.com" /></DomainGetListResult>
+<Paging><TotalItems>3</TotalItems><CurrentPage>1</CurrentPage><PageSize>2</PageSize></Paging>
+</CommandResponse></ApiResponse>"#
+                }
+                _ => {
+                    r#"<ApiResponse Status="OK"><CommandResponse><DomainGetListResult>
+<Domain ID="3" Name="three.com" /></DomainGetListResult>
+<Paging><TotalItems>3</TotalItems><CurrentPage>2</CurrentPage><PageSize>2</PageSize></Paging>
+</CommandResponse></ApiResponse>"#
+                }
+            };
+            return (200, body.to_string());
+        });
+        let account = account("Namecheap", &url);
+        assert_eq!(
+            domains(&account).unwrap(),
+            vec!["one.com", "two.com", "three.com"]
+        );
+        assert_eq!(requests.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn cloudflare_domains_pages() {
+        let (url, requests) = start_api(|request| {
+            let body = match request.query()["page"].as_str() {
+                "1" => {
+                    r#"{"success": true, "errors": [], "result": [{"name": "one.com"}, {"name": "Two.com"}],
+                    "result_info": {"page": 1, "per_page": 2, "count": 2, "total_count": 3, "total_pages": 2}}"#
+                }
+                _ => {
+                    r#"{"success": true, "errors": [], "result": [{"name": "three.com"}],
+                    "result_info": {"page": 2, "per_page": 2, "count": 1, "total_count": 3, "total_pages": 2}}"#
+                }
+            };
+            return (200, body.to_string());
+        });
+        let account = account("Cloudflare", &url);
+        assert_eq!(
+            domains(&account).unwrap(),
+            vec!["one.com", "two.com", "three.com"]
+        );
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1]
+            .target
+            .starts_with("/accounts/acc/registrar/domains?page=2"));
+    }
+
+    #[test]
+    fn account_keys_are_case_insensitive() {
+        let config: RegistrarAccountConfig =
+            serde_yaml::from_str("login: QweQwe\npassword: secret").unwrap();
+        let mut accounts = HashMap::new();
+        accounts.insert("reg.ru/qweqwe".to_string(), config.clone());
+        accounts.insert("RegRu-Main".to_string(), config);
+        let registrars = Registrars::new(&accounts);
+
+        let account = AccountConfig::from_string("Reg.ru - QweQwe");
+        assert!(registrars.contains(&account));
+        assert_eq!(
+            registrars.account(&account).unwrap().provider,
+            Provider::RegRu
+        );
+
+        let mut account = AccountConfig::from_string("reg.ru/other");
+        assert!(!registrars.contains(&account));
+        account.credentials_ref = "regru-MAIN".to_string();
+        assert!(registrars.contains(&account));
+        assert!(registrars.account(&account).is_some());
+    }
+
+    #[test]
+    fn xml_tags_with_same_prefix() {
+        let xml = r#"<DomainGetListResult><Domain ID="1" Name="a.com"/><DomainX/><Domain>text</Domain></DomainGetListResult>"#;
+        let tags = xml_tags(xml, "Domain");
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].0, r#" ID="1" Name="a.com"/"#);
+        assert_eq!(tags[1].0, "");
+        assert_eq!(xml_text(xml, "Domain"), Some("text".to_string()));
+        assert_eq!(
+            xml_text(xml, "DomainGetListResult"),
+            Some(r#"<Domain ID="1" Name="a.com"/><DomainX/><Domain>text</Domain>"#.to_string())
+        );
+        assert_eq!(xml_tags(xml, "DomainGetListResult").len(), 1);
+    }
+
+    #[test]
+    fn xml_attributes_unescaped() {
+        let attrs = xml_attributes(
+            r#" Name="a&amp;b.com" Title="&lt;&quot;x&quot;&gt; &apos;y&apos;" Empty=""/"#,
+        );
+        assert_eq!(attrs["Name"], "a&b.com");
+        assert_eq!(attrs["Title"], r#"<"x"> 'y'"#);
+        assert_eq!(attrs["Empty"], "");
+        assert_eq!(xml_unescape("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn xml_text_of_element() {
+        let xml = "<Errors><Error Number=\"1\"> Bad &amp; wrong </Error></Errors>";
+        assert_eq!(xml_text(xml, "Error"), Some("Bad & wrong".to_string()));
+        assert_eq!(xml_text(xml, "Warning"), None);
+    }
+}
@@ -0,0 +1,578 @@
+use {
+    crate::{
+        account_checker::AccountChecker,
+        archive::{Archive, ArchiveEntry},
+        cache::{CacheEntry, CachePolicy},
+        config::{Config, StateBackend},
+        customers_config::ascii_domain,
+        errors::{Error, Result},
+    },
+    chrono::{DateTime, Utc},
+    fs2::FileExt,
+    rusqlite::{params, Connection, Transaction, NO_PARAMS},
+    slog::{debug, error, info, Level},
+    slog_unwraps::ResultExt,
+    std::{
+        fs,
+        io::{self, Write},
+        path::Path,
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+// Interval of retry lock of state.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+// Migrations of sqlite schema, SQLITE_MIGRATIONS[n] upgrade database from version n to n + 1.
+// Version is stored in user_version pragma.
+const SQLITE_MIGRATIONS: &[&str] = &[
+    "
+CREATE TABLE IF NOT EXISTS domains (
+    domain TEXT PRIMARY KEY,
+    expire TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS lookups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    domain TEXT NOT NULL,
+    time TEXT NOT NULL,
+    expire TEXT,
+    error TEXT,
+    raw TEXT
+);
+CREATE INDEX IF NOT EXISTS lookups_domain_time ON lookups (domain, time);
+CREATE TABLE IF NOT EXISTS notifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    time TEXT NOT NULL,
+    customer TEXT NOT NULL,
+    dest TEXT NOT NULL,
+    email TEXT NOT NULL,
+    error TEXT
+);
+",
+    "
+ALTER TABLE domains ADD COLUMN fetched TEXT NOT NULL DEFAULT '';
+UPDATE domains SET fetched = strftime('%Y-%m-%dT%H:%M:%S+00:00', 'now');
+",
+    "
+ALTER TABLE lookups ADD COLUMN server TEXT;
+",
+    "
+ALTER TABLE domains ADD COLUMN autorenew INTEGER;
+",
+];
+
+// Result of one whois request.
+#[derive(Debug)]
+pub(crate) struct LookupRecord {
+    pub domain: String,
+
+    // Whois server, RDAP url or registrar API, which answered.
+    pub server: Option<String>,
+    pub time: DateTime<Utc>,
+    pub expire: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub raw: Option<String>,
+}
+
+// Report email, which was sent or failed.
+#[derive(Debug)]
+pub(crate) struct Notification {
+    pub time: DateTime<Utc>,
+    pub customer: String,
+    pub dest: &'static str,
+    pub email: String,
+    pub error: Option<String>,
+}
+
+// Advisory lock of state file, released on drop.
+pub(crate) struct StateLock {
+    file: fs::File,
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+// Lock state for whole run, so overlapped runs (cron, daemon) don't clobber each other.
+pub(crate) fn lock_state(log: &slog::Logger, cfg: &Config) -> Result<Option<StateLock>> {
+    if cfg.state_file.is_empty() {
+        return Ok(None);
+    }
+    let fname = format!("{}.lock", cfg.state_file);
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(&fname)
+        .log(log, Level::Error)?;
+    let deadline = Instant::now() + Duration::from_secs(cfg.state_lock_wait_seconds as u64);
+    let mut logged = false;
+    while file.try_lock_exclusive().is_err() {
+        if Instant::now() >= deadline {
+            error!(log, "State is locked by other instance"; "file"=>&fname);
+            return Err(Error::StateLocked(fname));
+        }
+        if !logged {
+            info!(log, "Wait lock of state"; "file"=>&fname);
+            logged = true;
+        }
+        thread::sleep(LOCK_RETRY_INTERVAL);
+    }
+    debug!(log, "State locked"; "file"=>&fname);
+    return Ok(Some(StateLock { file }));
+}
+
+// Write to temporary file, flush it to disk and rename over fname.
+// Crash in the middle of write leave old file untouched.
+fn write_atomic<F>(fname: &str, write: F) -> Result<()>
+where
+    F: FnOnce(&mut fs::File) -> Result<()>,
+{
+    let tmp_name = format!("{}.tmp", fname);
+    let mut file = fs::File::create(&tmp_name)?;
+    write(&mut file)?;
+    file.flush()?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_name, fname)?;
+
+    // Persist rename.
+    let dir = match Path::new(fname).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    return Ok(());
+}
+
+fn migrate_sqlite(log: &slog::Logger, conn: &mut Connection) -> Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+    if version as usize > SQLITE_MIGRATIONS.len() {
+        return Err(Error::StateVersionError(format!(
+            "version {} is newer then supported {}",
+            version,
+            SQLITE_MIGRATIONS.len()
+        )));
+    }
+    for (index, migration) in SQLITE_MIGRATIONS.iter().enumerate().skip(version as usize) {
+        info!(log, "Migrate sqlite state"; "version"=>index + 1);
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+        tx.commit()?;
+    }
+    return Ok(());
+}
+
+enum Backend {
+    None,
+    Yaml(String),
+    Sqlite(Connection),
+}
+
+pub(crate) struct Storage {
+    log: slog::Logger,
+    backend: Backend,
+    archive: Option<Archive>,
+}
+
+impl Storage {
+    pub(crate) fn open(log: &slog::Logger, cfg: &Config) -> Result<Self> {
+        let backend = if cfg.state_file.is_empty() {
+            debug!(log, "State file path is empty. Doesn't load state.");
+            Backend::None
+        } else {
+            match cfg.state_backend {
+                StateBackend::Yaml => Backend::Yaml(cfg.state_file.clone()),
+                StateBackend::Sqlite => {
+                    info!(log, "Open sqlite state"; "file"=>&cfg.state_file);
+                    let mut conn = Connection::open(&cfg.state_file).log(log, Level::Error)?;
+                    migrate_sqlite(log, &mut conn).log(log, Level::Error)?;
+                    Backend::Sqlite(conn)
+                }
+            }
+        };
+        return Ok(Storage {
+            log: log.clone(),
+            backend,
+            archive: Archive::from_config(cfg),
+        });
+    }
+
+    pub(crate) fn load(
+        &self,
+        log: &slog::Logger,
+        checker: &mut AccountChecker,
+        now: DateTime<Utc>,
+        policy: &CachePolicy,
+    ) -> Result<()> {
+        match &self.backend {
+            Backend::None => {}
+            Backend::Yaml(fname) => {
+                info!(log, "Load state"; "file"=>fname);
+                match fs::File::open(fname) {
+                    Err(err) => {
+                        if err.kind() == io::ErrorKind::NotFound {
+                            info!(log, "State file not found.")
+                        } else {
+                            error!(log, "State file error. You can remove it for reset cache.");
+                            return Err(Error::from(err));
+                        }
+                    }
+                    Ok(reader) => {
+                        // Ignore cache error
+                        let _ = checker
+                            .load_state(log, reader, now, policy)
+                            .log(log, Level::Error);
+                    }
+                }
+            }
+            Backend::Sqlite(conn) => {
+                let mut stmt =
+                    conn.prepare("SELECT domain, expire, fetched, autorenew FROM domains")?;
+                let rows = stmt.query_map(NO_PARAMS, |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<bool>>(3)?,
+                    ))
+                })?;
+                let cache = checker.cache_mut();
+                cache.clear();
+                for row in rows {
+                    let (domain, expire, fetched, autorenew) = row?;
+                    let entry = CacheEntry {
+                        expire: DateTime::parse_from_rfc3339(&expire)?.with_timezone(&Utc),
+                        fetched: DateTime::parse_from_rfc3339(&fetched)?.with_timezone(&Utc),
+                        autorenew,
+                    };
+                    // Old versions stored domains as written in customers config.
+                    cache.insert(&ascii_domain(&domain), entry);
+                }
+                checker.clean_cache(&now, policy);
+                info!(log, "Load cache"; "domains-count"=>checker.cache_mut().domains.len());
+            }
+        }
+        return Ok(());
+    }
+
+    // Save cache and lookups history of checker.
+    pub(crate) fn save(&mut self, checker: &mut AccountChecker) -> Result<()> {
+        let lookups = checker.take_lookups();
+        if let Some(archive) = &self.archive {
+            // Archive is for debug only, ignore errors.
+            let _ = archive
+                .save(&self.log, &lookups)
+                .log(&self.log, Level::Error);
+            let _ = archive
+                .clean(&self.log, &Utc::now())
+                .log(&self.log, Level::Error);
+        }
+        match &mut self.backend {
+            Backend::None => {}
+            Backend::Yaml(fname) => write_atomic(fname, |file| checker.save_state(file))?,
+            Backend::Sqlite(conn) => {
+                let tx = conn.transaction()?;
+                tx.execute("DELETE FROM domains", NO_PARAMS)?;
+                for (domain, entry) in checker.cache_mut().domains.iter() {
+                    tx.execute(
+                        "INSERT INTO domains (domain, expire, fetched, autorenew) VALUES (?1, ?2, ?3, ?4)",
+                        params![
+                            domain,
+                            entry.expire.to_rfc3339(),
+                            entry.fetched.to_rfc3339(),
+                            entry.autorenew
+                        ],
+                    )?;
+                }
+                insert_lookups(&tx, &lookups)?;
+                tx.commit()?;
+            }
+        }
+        return Ok(());
+    }
+
+    pub(crate) fn save_notifications(&mut self, notifications: &[Notification]) -> Result<()> {
+        if let Backend::Sqlite(conn) = &mut self.backend {
+            let tx = conn.transaction()?;
+            for item in notifications {
+                tx.execute(
+                    "INSERT INTO notifications (time, customer, dest, email, error) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        item.time.to_rfc3339(),
+                        item.customer,
+                        item.dest,
+                        item.email,
+                        item.error
+                    ],
+                )?;
+            }
+            tx.commit()?;
+        }
+        return Ok(());
+    }
+
+    // Last count raw whois responses of domain, from old to new.
+    pub(crate) fn whois_archive(&self, domain: &str, count: usize) -> Result<Vec<ArchiveEntry>> {
+        return match &self.archive {
+            Some(archive) => archive.last(domain, count),
+            None => Err(Error::ArchiveDisabled),
+        };
+    }
+
+    // Lookups of domain, from old to new.
+    pub(crate) fn domain_history(&self, domain: &str) -> Result<Vec<LookupRecord>> {
+        let conn = match &self.backend {
+            Backend::Sqlite(conn) => conn,
+            _ => return Err(Error::NeedSqliteStorage),
+        };
+        let mut stmt = conn.prepare(
+            "SELECT domain, time, expire, error, server FROM lookups WHERE domain = ?1 ORDER BY time, id",
+        )?;
+        let rows = stmt.query_map(params![domain], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        let mut res = Vec::new();
+        for row in rows {
+            let (domain, time, expire, error, server) = row?;
+            let expire = match expire {
+                Some(expire) => Some(DateTime::parse_from_rfc3339(&expire)?.with_timezone(&Utc)),
+                None => None,
+            };
+            res.push(LookupRecord {
+                domain,
+                server,
+                time: DateTime::parse_from_rfc3339(&time)?.with_timezone(&Utc),
+                expire,
+                error,
+                raw: None,
+            });
+        }
+        return Ok(res);
+    }
+}
+
+fn insert_lookups(tx: &Transaction, lookups: &[LookupRecord]) -> Result<()> {
+    for lookup in lookups {
+        tx.execute(
+            "INSERT INTO lookups (domain, time, expire, error, raw, server) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                lookup.domain,
+                lookup.time.to_rfc3339(),
+                lookup.expire.map(|expire| expire.to_rfc3339()),
+                lookup.error,
+                lookup.raw,
+                lookup.server
+            ],
+        )?;
+    }
+    return Ok(());
+}
+
+// Table of lookups with mark of renew: when expire date grow from previous successful lookup.
+pub(crate) fn create_history_report(history: &[LookupRecord]) -> String {
+    let mut table = vec![];
+    let mut prev_expire: Option<DateTime<Utc>> = None;
+    for item in history {
+        let renewed = match (prev_expire, item.expire) {
+            (Some(prev), Some(expire)) if expire > prev => "renewed",
+            _ => "",
+        };
+        if item.expire.is_some() {
+            prev_expire = item.expire;
+        }
+        table.push([
+            item.time.to_rfc3339(),
+            item.expire
+                .map(|expire| expire.to_rfc3339())
+                .or_else(|| item.error.clone())
+                .unwrap_or_default(),
+            item.server.clone().unwrap_or_default(),
+            renewed.to_string(),
+        ]);
+    }
+
+    let mut table_config = ascii_table::TableConfig::default();
+    table_config.width = 140;
+    for (index, header) in ["Lookup time", "Expired", "Server", "Renew"]
+        .iter()
+        .enumerate()
+    {
+        let mut column = ascii_table::ColumnConfig::default();
+        column.header = header.to_string();
+        table_config.columns.insert(index, column);
+    }
+    return ascii_table::format_table(&table, &table_config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // In-memory database of the version with one domain.
+    fn open_db(version: usize) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        for migration in &SQLITE_MIGRATIONS[..version] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.execute_batch(&format!("PRAGMA user_version = {}", version))
+            .unwrap();
+        match version {
+            0 => {}
+            1 => conn
+                .execute(
+                    "INSERT INTO domains (domain, expire) VALUES ('a.ru', '2027-01-01T00:00:00+00:00')",
+                    NO_PARAMS,
+                )
+                .map(|_| ())
+                .unwrap(),
+            _ => conn
+                .execute(
+                    "INSERT INTO domains (domain, expire, fetched) VALUES ('a.ru', '2027-01-01T00:00:00+00:00', '2020-01-01T00:00:00+00:00')",
+                    NO_PARAMS,
+                )
+                .map(|_| ())
+                .unwrap(),
+        }
+        return conn;
+    }
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap();
+        let rows = stmt
+            .query_map(NO_PARAMS, |row| row.get::<_, String>(1))
+            .unwrap();
+        return rows.map(|row| row.unwrap()).collect();
+    }
+
+    #[test]
+    fn migrate_sqlite_from_every_version() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        for version in 0..=SQLITE_MIGRATIONS.len() {
+            let mut conn = open_db(version);
+            migrate_sqlite(&log, &mut conn).unwrap();
+
+            let user_version: i64 = conn
+                .query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))
+                .unwrap();
+            assert_eq!(user_version as usize, SQLITE_MIGRATIONS.len());
+            assert_eq!(
+                columns(&conn, "domains"),
+                vec!["domain", "expire", "fetched", "autorenew"]
+            );
+            assert_eq!(
+                columns(&conn, "lookups"),
+                vec!["id", "domain", "time", "expire", "error", "raw", "server"]
+            );
+            assert_eq!(
+                columns(&conn, "notifications"),
+                vec!["id", "time", "customer", "dest", "email", "error"]
+            );
+
+            let rows: Vec<(String, String, String, Option<bool>)> = conn
+                .prepare("SELECT domain, expire, fetched, autorenew FROM domains")
+                .unwrap()
+                .query_map(NO_PARAMS, |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })
+                .unwrap()
+                .map(|row| row.unwrap())
+                .collect();
+            if version == 0 {
+                assert!(rows.is_empty());
+                continue;
+            }
+            assert_eq!(rows.len(), 1, "version {}", version);
+            let (domain, expire, fetched, autorenew) = &rows[0];
+            assert_eq!(domain, "a.ru");
+            assert_eq!(expire, "2027-01-01T00:00:00+00:00");
+            assert!(
+                DateTime::parse_from_rfc3339(fetched).is_ok(),
+                "version {}: fetched {}",
+                version,
+                fetched
+            );
+            if version >= 2 {
+                assert_eq!(fetched, "2020-01-01T00:00:00+00:00");
+            }
+            assert_eq!(*autorenew, None);
+        }
+    }
+
+    #[test]
+    fn history_with_server() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let mut conn = open_db(0);
+        migrate_sqlite(&log, &mut conn).unwrap();
+        let time = |time: &str| {
+            DateTime::parse_from_rfc3339(time)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        let tx = conn.transaction().unwrap();
+        insert_lookups(
+            &tx,
+            &[
+                LookupRecord {
+                    domain: "a.ru".to_string(),
+                    server: Some("whois.tcinet.ru".to_string()),
+                    time: time("2026-01-01T00:00:00Z"),
+                    expire: Some(time("2027-01-01T00:00:00Z")),
+                    error: None,
+                    raw: Some("paid-till: 2027-01-01T00:00:00Z".to_string()),
+                },
+                LookupRecord {
+                    domain: "a.ru".to_string(),
+                    server: Some("reg.ru API".to_string()),
+                    time: time("2026-02-01T00:00:00Z"),
+                    expire: Some(time("2028-01-01T00:00:00Z")),
+                    error: None,
+                    raw: None,
+                },
+            ],
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        let storage = Storage {
+            log: log.clone(),
+            backend: Backend::Sqlite(conn),
+            archive: None,
+        };
+        let history = storage.domain_history("a.ru").unwrap();
+        let servers: Vec<_> = history.iter().map(|item| item.server.clone()).collect();
+        assert_eq!(
+            servers,
+            vec![
+                Some("whois.tcinet.ru".to_string()),
+                Some("reg.ru API".to_string())
+            ]
+        );
+        let report = create_history_report(&history);
+        assert!(report.contains("whois.tcinet.ru"));
+        assert!(report.contains("renewed"));
+    }
+
+    #[test]
+    fn newer_sqlite_version() {
+        let log = slog::Logger::root(slog::Discard, slog::o!());
+        let mut conn = open_db(SQLITE_MIGRATIONS.len());
+        conn.execute_batch("PRAGMA user_version = 100").unwrap();
+        match migrate_sqlite(&log, &mut conn) {
+            Err(Error::StateVersionError(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+}
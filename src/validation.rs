@@ -0,0 +1,441 @@
+use {
+    crate::{
+        config::Config,
+        customers_config::{self, AccountConfig, CustomersFile},
+        errors::Error,
+        public_suffix::SuffixList,
+        registrar::{self, Registrars},
+    },
+    chrono::NaiveTime,
+    std::{collections::HashMap, fmt, fs, net::IpAddr, path::Path, str::FromStr},
+    yaml_rust::{
+        parser::{Event, MarkedEventReceiver, Parser},
+        scanner::Marker,
+    },
+};
+
+// Prefix of disabled email in customers file.
+const EMAIL_OFF_PREFIX: &str = "OFF:";
+
+// Problem of config with location.
+pub(crate) struct Problem {
+    // Warning doesn't make config invalid.
+    pub warning: bool,
+    pub file: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = if self.warning { "warning" } else { "error" };
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}: {}", self.file, line, level, self.message),
+            None => write!(f, "{}: {}: {}", self.file, level, self.message),
+        }
+    }
+}
+
+// Lines of scalars in yaml file. serde_yaml doesn't keep positions of values,
+// so value is found by text: n-th request of the value returns line of n-th occurrence.
+struct Locator {
+    scalars: Vec<(String, usize)>,
+    seen: HashMap<String, usize>,
+}
+
+impl MarkedEventReceiver for Locator {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        if let Event::Scalar(value, ..) = ev {
+            self.scalars.push((value, mark.line()));
+        }
+    }
+}
+
+impl Locator {
+    fn from_file(path: &Path) -> Self {
+        let mut res = Locator {
+            scalars: Vec::new(),
+            seen: HashMap::new(),
+        };
+        if let Ok(text) = fs::read_to_string(path) {
+            let mut parser = Parser::new(text.chars());
+            // Parse errors are reported by serde_yaml.
+            let _ = parser.load(&mut res, true);
+        }
+        return res;
+    }
+
+    // Line of next occurrence of value.
+    fn next_line(&mut self, value: &str) -> Option<usize> {
+        let index = self.seen.entry(value.to_string()).or_insert(0);
+        let line = self
+            .scalars
+            .iter()
+            .filter(|(scalar, _)| scalar == value)
+            .nth(*index)
+            .map(|(_, line)| *line);
+        *index += 1;
+        return line;
+    }
+
+    // Line of first occurrence of value, for keys of config.
+    fn first_line(&self, value: &str) -> Option<usize> {
+        return self
+            .scalars
+            .iter()
+            .find(|(scalar, _)| scalar == value)
+            .map(|(_, line)| *line);
+    }
+}
+
+// Same customer name or same domain in two places.
+pub(crate) fn find_duplicates(files: &[CustomersFile]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut names: HashMap<&str, String> = HashMap::new();
+    let mut domains: HashMap<String, (&str, String)> = HashMap::new();
+    for file in files {
+        let fname = file.path.to_string_lossy().to_string();
+        let mut locator = Locator::from_file(&file.path);
+        for customer in &file.customers {
+            let line = locator.next_line(&customer.name);
+            let place = location(&fname, line);
+            if let Some(first) = names.insert(&customer.name, place) {
+                problems.push(Problem {
+                    warning: false,
+                    file: fname.clone(),
+                    line,
+                    message: format!(
+                        "duplicate customer {}, first defined at {}",
+                        customer.name, first
+                    ),
+                });
+            }
+            for domain in &customer.domains {
+                let line = locator.next_line(&domain.domain);
+                let key = customers_config::normalize_domain(&domain.domain)
+                    .unwrap_or_else(|_| domain.domain.to_lowercase());
+                if let Some((name, first)) = domains.get(&key) {
+                    problems.push(Problem {
+                        warning: false,
+                        file: fname.clone(),
+                        line,
+                        message: format!(
+                            "duplicate domain {} of customer {}, first defined at {} for customer {}",
+                            domain.domain, customer.name, first, name
+                        ),
+                    });
+                } else {
+                    domains.insert(key, (&customer.name, location(&fname, line)));
+                }
+            }
+        }
+    }
+    return problems;
+}
+
+fn location(fname: &str, line: Option<usize>) -> String {
+    return match line {
+        Some(line) => format!("{}:{}", fname, line),
+        None => fname.to_string(),
+    };
+}
+
+// Email without case insensitive prefix of disabled email.
+fn without_off_prefix(email: &str) -> &str {
+    match email.get(..EMAIL_OFF_PREFIX.len()) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(EMAIL_OFF_PREFIX) => {
+            return &email[EMAIL_OFF_PREFIX.len()..];
+        }
+        _ => return email,
+    }
+}
+
+// Simple syntax check: local@domain, without spaces, valid domain.
+fn check_email(email: &str) -> std::result::Result<(), String> {
+    if email.is_empty() {
+        return Err("empty email".to_string());
+    }
+    let parts: Vec<&str> = email.split('@').collect();
+    if parts.len() != 2 {
+        return Err(format!("bad email {}: need exactly one @", email));
+    }
+    let (local, domain) = (parts[0], parts[1]);
+    let is_local_valid = !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && !"()<>[]:;,\\\"".contains(c));
+    if !is_local_valid {
+        return Err(format!("bad email {}: bad local part", email));
+    }
+    if domain.ends_with('.') {
+        return Err(format!("bad email {}: domain ends with dot", email));
+    }
+    return customers_config::normalize_domain(domain)
+        .map(|_| ())
+        .map_err(|err| format!("bad email {}: {}", email, err));
+}
+
+// Empty or socks5://, socks5h://, http:// url with host.
+fn check_proxy(proxy: &str) -> std::result::Result<(), String> {
+    if proxy.is_empty() {
+        return Ok(());
+    }
+    let url = url::Url::parse(proxy).map_err(|err| format!("bad url {}: {}", proxy, err))?;
+    if !["socks5", "socks5h", "http"].contains(&url.scheme()) {
+        return Err(format!("unsupported scheme {}", url.scheme()));
+    }
+    if url.host_str().is_none() {
+        return Err(format!("no host in {}", proxy));
+    }
+    return Ok(());
+}
+
+// Credentials of account exist and provider is known.
+fn check_registrar_account(
+    registrars: &Registrars,
+    account: &AccountConfig,
+) -> std::result::Result<(), String> {
+    let key = account.credentials_key();
+    if !registrars.contains(account) {
+        if account.credentials_ref.is_empty() {
+            // Account without API.
+            return Ok(());
+        }
+        return Err(format!("{} isn't found in registrar_accounts", key));
+    }
+    return match registrars.account(account) {
+        Some(registrar_account) => registrar_account
+            .check_credentials()
+            .map_err(|err| format!("{}: {}", key, err)),
+        None => Err(format!(
+            "{}: provider is unknown for registrar {}, set provider",
+            key, account.registrar
+        )),
+    };
+}
+
+// All problems of config and customers files.
+pub(crate) fn validate(cfg: &Config, config_file: &str) -> Vec<Problem> {
+    let mut problems = validate_config(cfg, config_file);
+    let suffix_list = if cfg.public_suffix_list.is_empty() {
+        SuffixList::empty()
+    } else {
+        match SuffixList::from_file(&cfg.public_suffix_list) {
+            Ok(list) => list,
+            // Runtime warns and checks domains as is, so it isn't error.
+            Err(err) => {
+                problems.push(Problem {
+                    warning: true,
+                    file: config_file.to_string(),
+                    line: Locator::from_file(Path::new(config_file))
+                        .first_line("public_suffix_list"),
+                    message: format!("public_suffix_list: {}, domains are checked as is", err),
+                });
+                SuffixList::empty()
+            }
+        }
+    };
+    match customers_config::load_customers_files(&cfg.customers_file) {
+        Ok(files) => {
+            problems.extend(validate_customers(cfg, &files, &suffix_list));
+            problems.extend(find_duplicates(&files));
+        }
+        Err(Error::CustomersFileError(fname, err)) => problems.push(Problem {
+            warning: false,
+            file: fname,
+            line: err.location().map(|location| location.line()),
+            message: err.to_string(),
+        }),
+        Err(err) => problems.push(Problem {
+            warning: false,
+            file: cfg.customers_file.join(", "),
+            line: None,
+            message: err.to_string(),
+        }),
+    }
+    return problems;
+}
+
+fn validate_config(cfg: &Config, config_file: &str) -> Vec<Problem> {
+    let locator = Locator::from_file(Path::new(config_file));
+    let mut problems = Vec::new();
+    let mut add = |key: &str, message: String| {
+        problems.push(Problem {
+            warning: false,
+            file: config_file.to_string(),
+            line: locator.first_line(key),
+            message: format!("{}: {}", key, message),
+        });
+    };
+
+    if cfg.smtp_server.trim().is_empty() {
+        add("smtp_server", "must not be empty".to_string());
+    }
+    if cfg.smtp_port == 0 {
+        add("smtp_port", "must not be 0".to_string());
+    }
+    if let Err(err) = check_email(&cfg.smtp_from) {
+        add("smtp_from", err);
+    }
+    for email in &cfg.admin_emails {
+        if let Err(err) = check_email(without_off_prefix(email)) {
+            add("admin_emails", err);
+        }
+    }
+    if cfg.ok_report_day > 7 {
+        add(
+            "ok_report_day",
+            format!(
+                "must be 0 (every day) or day of week 1-7, got {}",
+                cfg.ok_report_day
+            ),
+        );
+    }
+    if cfg.expire_soon_days == 0 {
+        add("expire_soon_days", "must be greater then 0".to_string());
+    }
+    if !cfg.daemon_check_schedule.is_empty() {
+        if let Err(err) = cron::Schedule::from_str(&cfg.daemon_check_schedule) {
+            add("daemon_check_schedule", err.to_string());
+        }
+    } else if cfg.daemon_check_interval_minutes == 0 {
+        add(
+            "daemon_check_interval_minutes",
+            "must be greater then 0".to_string(),
+        );
+    }
+    if let Err(err) = NaiveTime::parse_from_str(&cfg.daemon_report_time, "%H:%M") {
+        add("daemon_report_time", format!("need HH:MM: {}", err));
+    }
+    if cfg.customers_file.is_empty() {
+        add("customers_file", "must not be empty".to_string());
+    }
+    if let Err(err) = check_proxy(&cfg.whois_proxy) {
+        add("whois_proxy", err);
+    }
+    for ip in &cfg.whois_source_ips {
+        if ip.parse::<IpAddr>().is_err() {
+            add("whois_source_ips", format!("bad address {}", ip));
+        }
+    }
+    for (name, account) in &cfg.registrar_accounts {
+        // Provider of account can be known from registrar of domains only,
+        // such accounts are checked with customers.
+        if let Ok(account) = registrar::resolve(name, account) {
+            if let Err(err) = account.check_credentials() {
+                add(name, err);
+            }
+        }
+        if let Err(err) = check_proxy(&account.proxy) {
+            add(name, format!("proxy: {}", err));
+        }
+    }
+    for (zone, server) in &cfg.whois_servers {
+        if let Err(err) = check_proxy(server.proxy.as_ref().map_or("", String::as_str)) {
+            add(zone, format!("proxy: {}", err));
+        }
+        for ip in server.source_ips.iter().flatten() {
+            if ip.parse::<IpAddr>().is_err() {
+                add(zone, format!("source_ips: bad address {}", ip));
+            }
+        }
+    }
+    return problems;
+}
+
+fn validate_customers(
+    cfg: &Config,
+    files: &[CustomersFile],
+    suffix_list: &SuffixList,
+) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let registrars = Registrars::from_config(cfg);
+    for file in files {
+        let fname = file.path.to_string_lossy().to_string();
+        let mut locator = Locator::from_file(&file.path);
+        for customer in &file.customers {
+            let line = locator.next_line(&customer.name);
+            if customer.name.trim().is_empty() {
+                problems.push(Problem {
+                    warning: false,
+                    file: fname.clone(),
+                    line,
+                    message: "empty customer name".to_string(),
+                });
+            }
+            if customer.emails.is_empty() && !customer.disabled {
+                problems.push(Problem {
+                    warning: false,
+                    file: fname.clone(),
+                    line,
+                    message: format!("customer {} has no emails", customer.name),
+                });
+            }
+            for email in &customer.emails {
+                let line = locator.next_line(email);
+                if let Err(err) = check_email(without_off_prefix(email)) {
+                    problems.push(Problem {
+                        warning: false,
+                        file: fname.clone(),
+                        line,
+                        message: err,
+                    });
+                }
+            }
+            for domain in &customer.domains {
+                let line = locator.next_line(&domain.domain);
+                if let Err(err) = check_registrar_account(&registrars, &domain.account) {
+                    problems.push(Problem {
+                        warning: false,
+                        file: fname.clone(),
+                        line,
+                        message: format!("account of domain {}: {}", domain.domain, err),
+                    });
+                }
+                match customers_config::normalize_domain(&domain.domain) {
+                    Err(err) => problems.push(Problem {
+                        warning: false,
+                        file: fname.clone(),
+                        line,
+                        message: err,
+                    }),
+                    Ok(name) => {
+                        let registrable = suffix_list.registrable_domain(&name);
+                        if registrable != name {
+                            problems.push(Problem {
+                                warning: true,
+                                file: fname.clone(),
+                                line,
+                                message: format!(
+                                    "{} isn't registrable domain, registration of {} is checked",
+                                    domain.domain,
+                                    customers_config::unicode_domain(&registrable)
+                                ),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    }
+    return problems;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_prefix_is_case_insensitive() {
+        assert_eq!(without_off_prefix("OFF:a@b.ru"), "a@b.ru");
+        assert_eq!(without_off_prefix("off:a@b.ru"), "a@b.ru");
+        assert_eq!(without_off_prefix("Off:a@b.ru"), "a@b.ru");
+        assert_eq!(without_off_prefix("a@b.ru"), "a@b.ru");
+        assert_eq!(without_off_prefix("оф"), "оф");
+        assert!(check_email(without_off_prefix("off:a@b.ru")).is_ok());
+    }
+}
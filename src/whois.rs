@@ -0,0 +1,569 @@
+use {
+    crate::{
+        config::Config,
+        customers_config::ascii_domain,
+        errors::{Error, Result},
+        net::{self, Route},
+    },
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::HashMap,
+        io::{Read, Write},
+    },
+};
+
+const ROOT_WHOIS_SERVER: &str = "whois.iana.org";
+const WHOIS_PORT: u16 = 43;
+const MAX_RDAP_REDIRECTS: usize = 5;
+
+// Placeholder of domain name in query template.
+const DOMAIN_PLACEHOLDER: &str = "{domain}";
+
+// Whois server for zone or domain.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub(crate) struct WhoisServerConfig {
+    // host, host:port or RDAP base URL (https://rdap.example/v1/).
+    pub server: String,
+
+    // Query template for whois server, for example "-T dn {domain}". Default: "{domain}".
+    // Template without {domain} is prefix: domain is added after space.
+    #[serde(default)]
+    pub query: String,
+
+    // Override whois_proxy for this server, "" - connect directly.
+    #[serde(default)]
+    pub proxy: Option<String>,
+
+    // Override whois_source_ips for this server, [] - default source address.
+    #[serde(default)]
+    pub source_ips: Option<Vec<String>>,
+}
+
+impl WhoisServerConfig {
+    fn is_rdap(&self) -> bool {
+        return self.server.starts_with("http://") || self.server.starts_with("https://");
+    }
+}
+
+// Response of whois or RDAP server.
+pub(crate) struct Response {
+    pub server: String,
+    pub text: String,
+}
+
+pub(crate) struct WhoisClient {
+    // Servers from config: ascii zone -> server.
+    servers: HashMap<String, WhoisServerConfig>,
+
+    // Whois servers of zones, found by referrals from root server.
+    referrals: HashMap<String, String>,
+
+    // Default proxy url and pool of source addresses.
+    proxy: String,
+    source_ips: Vec<String>,
+
+    // Lookups count, for rotation of source addresses.
+    lookups: usize,
+}
+
+impl WhoisClient {
+    pub(crate) fn from_config(cfg: &Config) -> Self {
+        return WhoisClient {
+            servers: cfg
+                .whois_servers
+                .iter()
+                .map(|(zone, server)| (ascii_domain(zone), server.clone()))
+                .collect(),
+            referrals: HashMap::new(),
+            proxy: cfg.whois_proxy.clone(),
+            source_ips: cfg.whois_source_ips.clone(),
+            lookups: 0,
+        };
+    }
+
+    // Route of next lookup: settings of server override defaults,
+    // source addresses of pool are used in turn.
+    fn route(&mut self, server: Option<&WhoisServerConfig>) -> Result<Route> {
+        let proxy = server
+            .and_then(|server| server.proxy.as_ref())
+            .unwrap_or(&self.proxy);
+        let source_ips = server
+            .and_then(|server| server.source_ips.as_ref())
+            .unwrap_or(&self.source_ips);
+        let source_ip = if source_ips.is_empty() {
+            None
+        } else {
+            let ip = &source_ips[self.lookups % source_ips.len()];
+            Some(
+                ip.parse()
+                    .map_err(|_| Error::WhoisError(format!("Bad source address: {}", ip)))?,
+            )
+        };
+        self.lookups += 1;
+        return Ok(Route {
+            proxy: proxy.clone(),
+            source_ip,
+        });
+    }
+
+    // Lookup ascii domain name. Server of domain overrides servers from config,
+    // without configured server whois servers are found by referrals from IANA.
+    pub(crate) fn lookup(
+        &mut self,
+        domain: &str,
+        server: Option<&WhoisServerConfig>,
+    ) -> Result<Response> {
+        let server = match server {
+            Some(server) => Some(server.clone()),
+            None => zones(domain)
+                .into_iter()
+                .find_map(|zone| self.servers.get(zone))
+                .cloned(),
+        };
+        if let Some(server) = server {
+            let route = self.route(Some(&server))?;
+            let text = if server.is_rdap() {
+                rdap_query(&route, &server.server, domain)?
+            } else {
+                whois_query(&route, &server.server, &server.query, domain)?.to_lowercase()
+            };
+            return Ok(Response {
+                server: server.server.clone(),
+                text,
+            });
+        }
+        return self.lookup_by_referrals(domain);
+    }
+
+    fn lookup_by_referrals(&mut self, domain: &str) -> Result<Response> {
+        let mut server = zones(domain)
+            .into_iter()
+            .find_map(|zone| self.referrals.get(zone))
+            .cloned()
+            .unwrap_or_else(|| ROOT_WHOIS_SERVER.to_string());
+        let route = self.route(None)?;
+        let mut visited = Vec::new();
+        loop {
+            visited.push(server.clone());
+            let text = whois_query(&route, &server, "", domain)?.to_lowercase();
+            let whois = key_value(&text);
+            let whois_domain = whois
+                .get("domain")
+                .or_else(|| whois.get("domain name"))
+                .cloned();
+            if whois_domain == Some(domain) {
+                return Ok(Response { server, text });
+            }
+            let next_server = match whois.get("whois").or_else(|| whois.get("refer")) {
+                Some(next_server) => next_server.to_string(),
+                None => return Err(Error::WhoisError("Can't find whois server".to_string())),
+            };
+            if visited.contains(&next_server) {
+                return Err(Error::WhoisError(format!(
+                    "Whois server loop: {}",
+                    next_server
+                )));
+            }
+            self.referrals.insert(
+                whois_domain.unwrap_or(domain).to_string(),
+                next_server.clone(),
+            );
+            server = next_server;
+        }
+    }
+}
+
+// Domain and its parent zones, from longest: a.example.com, example.com, com.
+fn zones(domain: &str) -> Vec<&str> {
+    let mut res = vec![domain];
+    for (index, _) in domain.match_indices('.') {
+        res.push(&domain[index + 1..]);
+    }
+    return res;
+}
+
+// Fields of whois response. First value is used, if key is repeated.
+pub(crate) fn key_value(text: &str) -> HashMap<&str, &str> {
+    let mut res = HashMap::new();
+    for line in text.lines() {
+        let mut parts = line.trim().splitn(2, ':');
+        if let (Some(key), Some(val)) = (parts.next(), parts.next()) {
+            res.entry(key.trim()).or_insert_with(|| val.trim());
+        }
+    }
+    return res;
+}
+
+// host, host:port, IPv6 address or [IPv6 address]:port.
+fn split_host_port(server: &str, default_port: u16) -> Result<(&str, u16)> {
+    let bad_server = || Error::WhoisError(format!("Bad whois server: {}", server));
+    let parse_port = |port: &str| {
+        return port
+            .parse()
+            .map_err(|_| Error::WhoisError(format!("Bad port of server: {}", server)));
+    };
+    if server.starts_with('[') {
+        let end = server.find(']').ok_or_else(bad_server)?;
+        let host = &server[1..end];
+        let rest = &server[end + 1..];
+        if rest.is_empty() {
+            return Ok((host, default_port));
+        }
+        if !rest.starts_with(':') {
+            return Err(bad_server());
+        }
+        return Ok((host, parse_port(&rest[1..])?));
+    }
+    // IPv6 address without port.
+    if server.matches(':').count() > 1 {
+        return Ok((server, default_port));
+    }
+    match server.find(':') {
+        Some(index) => return Ok((&server[..index], parse_port(&server[index + 1..])?)),
+        None => return Ok((server, default_port)),
+    }
+}
+
+fn whois_query(route: &Route, server: &str, query: &str, domain: &str) -> Result<String> {
+    let (host, port) = split_host_port(server, WHOIS_PORT)?;
+    let query = if query.is_empty() {
+        domain.to_string()
+    } else if query.contains(DOMAIN_PLACEHOLDER) {
+        query.replace(DOMAIN_PLACEHOLDER, domain)
+    } else {
+        format!("{} {}", query.trim_end(), domain)
+    };
+    let mut conn = net::connect(route, host, port)?;
+    conn.write_all(format!("{}\r\n", query).as_bytes())?;
+    conn.flush()?;
+    let mut res = Vec::new();
+    conn.read_to_end(&mut res)?;
+    // Some servers answer in legacy encodings.
+    return Ok(String::from_utf8_lossy(&res).to_string());
+}
+
+// Get domain object from RDAP server: <base url>/domain/<domain>.
+fn rdap_query(route: &Route, base_url: &str, domain: &str) -> Result<String> {
+    let mut url = format!("{}/domain/{}", base_url.trim_end_matches('/'), domain);
+    for _ in 0..MAX_RDAP_REDIRECTS {
+        let res = net::http_request(
+            route,
+            "GET",
+            &url,
+            &[("Accept", "application/rdap+json".to_string())],
+            "",
+        )?;
+        match res.status {
+            200 => return Ok(res.body),
+            301 | 302 | 303 | 307 | 308 => match res.headers.get("location") {
+                // Location can be relative to url of request.
+                Some(location) => {
+                    url = url::Url::parse(&url)
+                        .and_then(|base| base.join(location))
+                        .map_err(|err| {
+                            Error::WhoisError(format!("Bad RDAP redirect {}: {}", location, err))
+                        })?
+                        .into_string();
+                }
+                None => break,
+            },
+            _ => {
+                return Err(Error::WhoisError(format!(
+                    "RDAP server returned status {}: {}",
+                    res.status, url
+                )))
+            }
+        }
+    }
+    return Err(Error::WhoisError(format!(
+        "Too many RDAP redirects: {}",
+        url
+    )));
+}
+
+// Expiration date from RDAP domain object: events[eventAction=expiration].eventDate
+pub(crate) fn rdap_expire_date(text: &str) -> Option<String> {
+    let value = json::parse(text).ok()?;
+    for event in value["events"].members() {
+        if event["eventAction"].as_str() == Some("expiration") {
+            return event["eventDate"].as_str().map(str::to_string);
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            io::{BufRead, BufReader},
+            net::TcpListener,
+            sync::{Arc, Mutex},
+            thread,
+        },
+    };
+
+    // Local whois server: answers queries by answers, unknown query - "no match".
+    // Returns address of server and received queries.
+    fn start_whois(answers: Vec<(String, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let queries = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&queries);
+        thread::spawn(move || {
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                let mut line = String::new();
+                BufReader::new(&conn).read_line(&mut line).unwrap();
+                let query = line.trim_end().to_string();
+                let answer = answers
+                    .iter()
+                    .find(|(known, _)| *known == query)
+                    .map_or("No match\n", |(_, answer)| answer.as_str());
+                conn.write_all(answer.as_bytes()).unwrap();
+                received.lock().unwrap().push(query);
+            }
+        });
+        return (addr, queries);
+    }
+
+    // Local http server: path -> status line, headers and body of response.
+    fn start_http(responses: Vec<(&'static str, &'static str)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                let mut reader = BufReader::new(&conn);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                let response = responses
+                    .iter()
+                    .find(|(known, _)| *known == path)
+                    .map_or("HTTP/1.0 404 Not Found\r\n\r\n", |(_, response)| response);
+                conn.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        return addr;
+    }
+
+    fn client() -> WhoisClient {
+        return WhoisClient {
+            servers: HashMap::new(),
+            referrals: HashMap::new(),
+            proxy: String::new(),
+            source_ips: Vec::new(),
+            lookups: 0,
+        };
+    }
+
+    fn server_config(server: &str, query: &str) -> WhoisServerConfig {
+        return WhoisServerConfig {
+            server: server.to_string(),
+            query: query.to_string(),
+            proxy: None,
+            source_ips: None,
+        };
+    }
+
+    fn answer(query: &str, answer: &str) -> (String, String) {
+        return (query.to_string(), answer.to_string());
+    }
+
+    #[test]
+    fn configured_server_with_template() {
+        let (addr, queries) = start_whois(vec![answer(
+            "-T dn example.de",
+            "Domain: example.de\nStatus: connect\n",
+        )]);
+        let mut client = client();
+        client
+            .servers
+            .insert("de".to_string(), server_config(&addr, "-T dn {domain}"));
+        let res = client.lookup("example.de", None).unwrap();
+        assert_eq!(res.server, addr);
+        assert_eq!(res.text, "domain: example.de\nstatus: connect\n");
+        assert_eq!(*queries.lock().unwrap(), vec!["-T dn example.de"]);
+    }
+
+    #[test]
+    fn query_prefix_without_placeholder() {
+        let (addr, queries) = start_whois(vec![]);
+        let mut client = client();
+        for query in &["-T dn", "domain "] {
+            client
+                .lookup("example.de", Some(&server_config(&addr, query)))
+                .unwrap();
+        }
+        assert_eq!(
+            *queries.lock().unwrap(),
+            vec!["-T dn example.de", "domain example.de"]
+        );
+    }
+
+    #[test]
+    fn domain_server_overrides_config() {
+        let (config_addr, config_queries) = start_whois(vec![]);
+        let (domain_addr, domain_queries) = start_whois(vec![]);
+        let mut client = client();
+        client
+            .servers
+            .insert("de".to_string(), server_config(&config_addr, ""));
+        let res = client
+            .lookup("example.de", Some(&server_config(&domain_addr, "")))
+            .unwrap();
+        assert_eq!(res.server, domain_addr);
+        assert!(config_queries.lock().unwrap().is_empty());
+        assert_eq!(*domain_queries.lock().unwrap(), vec!["example.de"]);
+    }
+
+    #[test]
+    fn referral_chain() {
+        let (registry_addr, registry_queries) = start_whois(vec![
+            answer(
+                "example.test",
+                "Domain Name: EXAMPLE.TEST\nRegistry Expiry Date: 2027-01-01T00:00:00Z\n",
+            ),
+            answer("other.test", "Domain Name: OTHER.TEST\n"),
+        ]);
+        let (root_addr, root_queries) = start_whois(vec![answer(
+            "example.test",
+            &format!("domain: TEST\nrefer: {}\n", registry_addr),
+        )]);
+        let mut client = client();
+        client
+            .referrals
+            .insert("test".to_string(), root_addr.clone());
+
+        let res = client.lookup("example.test", None).unwrap();
+        assert_eq!(res.server, registry_addr);
+        assert!(res
+            .text
+            .contains("registry expiry date: 2027-01-01t00:00:00z"));
+        assert_eq!(client.referrals.get("test"), Some(&registry_addr));
+
+        // Next domain of zone is asked from registry server directly.
+        let res = client.lookup("other.test", None).unwrap();
+        assert_eq!(res.server, registry_addr);
+        assert_eq!(*root_queries.lock().unwrap(), vec!["example.test"]);
+        assert_eq!(
+            *registry_queries.lock().unwrap(),
+            vec!["example.test", "other.test"]
+        );
+    }
+
+    #[test]
+    fn referral_loop() {
+        let (addr, _) = start_whois(vec![]);
+        let (root_addr, _) =
+            start_whois(vec![answer("example.test", &format!("refer: {}\n", addr))]);
+        let mut client = client();
+        client.referrals.insert("test".to_string(), root_addr);
+        assert!(client.lookup("example.test", None).is_err());
+    }
+
+    #[test]
+    fn host_and_port() {
+        assert_eq!(
+            split_host_port("whois.denic.de", 43).unwrap(),
+            ("whois.denic.de", 43)
+        );
+        assert_eq!(
+            split_host_port("whois.denic.de:4343", 43).unwrap(),
+            ("whois.denic.de", 4343)
+        );
+        assert_eq!(
+            split_host_port("2001:db8::1", 43).unwrap(),
+            ("2001:db8::1", 43)
+        );
+        assert_eq!(
+            split_host_port("[2001:db8::1]", 43).unwrap(),
+            ("2001:db8::1", 43)
+        );
+        assert_eq!(
+            split_host_port("[2001:db8::1]:4343", 43).unwrap(),
+            ("2001:db8::1", 4343)
+        );
+        assert!(split_host_port("whois.denic.de:port", 43).is_err());
+        assert!(split_host_port("[2001:db8::1]4343", 43).is_err());
+    }
+
+    #[test]
+    fn first_value_of_repeated_key() {
+        let text = "domain: EXAMPLE.RU\npaid-till: 2027-01-01T00:00:00Z\npaid-till: 2020-01-01T00:00:00Z\n";
+        let fields = key_value(text);
+        assert_eq!(fields["paid-till"], "2027-01-01T00:00:00Z");
+        assert_eq!(
+            crate::account_checker::parse_expire_date(text).unwrap(),
+            chrono::DateTime::parse_from_rfc3339("2027-01-01T00:00:00Z").unwrap()
+        );
+    }
+
+    #[test]
+    fn rdap_relative_redirect() {
+        let addr = start_http(vec![
+            (
+                "/v1/domain/example.com",
+                "HTTP/1.0 301 Moved Permanently\r\nLocation: /v2/domain/example.com\r\n\r\n",
+            ),
+            (
+                "/v2/domain/example.com",
+                "HTTP/1.0 200 OK\r\n\r\n{\"events\": [{\"eventAction\": \"expiration\", \"eventDate\": \"2027-01-01T00:00:00Z\"}]}",
+            ),
+        ]);
+        let text = rdap_query(
+            &Route::direct(),
+            &format!("http://{}/v1/", addr),
+            "example.com",
+        )
+        .unwrap();
+        assert_eq!(
+            rdap_expire_date(&text),
+            Some("2027-01-01T00:00:00Z".to_string())
+        );
+    }
+
+    #[test]
+    fn rotation_of_source_addresses() {
+        let mut client = client();
+        client.proxy = "socks5://127.0.0.1:1080".to_string();
+        client.source_ips = vec!["127.0.0.1".to_string(), "127.0.0.2".to_string()];
+        let ips: Vec<String> = (0..3)
+            .map(|_| client.route(None).unwrap().source_ip.unwrap().to_string())
+            .collect();
+        assert_eq!(ips, vec!["127.0.0.1", "127.0.0.2", "127.0.0.1"]);
+
+        // Settings of server override pool and proxy.
+        let mut server = server_config("whois.example", "");
+        server.proxy = Some(String::new());
+        server.source_ips = Some(vec!["127.0.0.3".to_string()]);
+        let route = client.route(Some(&server)).unwrap();
+        assert_eq!(route.proxy, "");
+        assert_eq!(route.source_ip.unwrap().to_string(), "127.0.0.3");
+
+        let route = client
+            .route(Some(&server_config("whois.example", "")))
+            .unwrap();
+        assert_eq!(route.proxy, "socks5://127.0.0.1:1080");
+        assert_eq!(route.source_ip.unwrap().to_string(), "127.0.0.1");
+    }
+
+    #[test]
+    fn bad_source_address() {
+        let mut client = client();
+        client.source_ips = vec!["bad".to_string()];
+        assert!(client.route(None).is_err());
+    }
+
+    #[test]
+    fn rdap_error_status() {
+        let addr = start_http(vec![]);
+        assert!(rdap_query(&Route::direct(), &format!("http://{}", addr), "example.com").is_err());
+    }
+}